@@ -0,0 +1,114 @@
+use crate::core::canonical::{Canonical, CanonicalValue};
+use crate::core::price::Price;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ApportionmentError {
+    #[error("cannot apportion liability across an empty delegation chain")]
+    EmptyChain,
+    #[error("weighted apportionment needs at least one weight")]
+    NoWeights,
+    #[error("apportionment weights must not all be zero")]
+    ZeroWeights,
+}
+
+/// One party's share of a liability record's price.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LiabilityLine {
+    pub party: String,
+    pub amount: Price,
+}
+
+impl Canonical for LiabilityLine {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("party", CanonicalValue::str(&self.party)),
+            ("amount", self.amount.canonical_value()),
+        ])
+    }
+}
+
+/// How the price of an execution is divided among the principals of the
+/// unit's delegation chain, issuer first. `IssuerTakesAll` charges the
+/// unit's issuer, the party that signed it, in a single line.
+///
+/// Shares that do not divide evenly are rounded down and the remaining units
+/// go to the parties with the largest fractional shares, earlier principals
+/// first on ties, so the line items always sum exactly to the price. A
+/// principal appearing at several positions receives a single line with the
+/// combined share.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Apportionment {
+    #[default]
+    IssuerTakesAll,
+    EqualSplit,
+    /// Weights by position in the delegation chain, issuer first. Principals
+    /// past the end of the list take its last weight, and weights past the
+    /// end of the chain are unused, so any chain length is accepted.
+    Weighted(Vec<u64>),
+}
+
+impl Apportionment {
+    pub fn apportion(
+        &self,
+        issuer: &str,
+        chain: &[String],
+        price: &Price,
+    ) -> Result<Vec<LiabilityLine>, ApportionmentError> {
+        if chain.is_empty() {
+            return Err(ApportionmentError::EmptyChain);
+        }
+        let weights = match self {
+            Apportionment::IssuerTakesAll => {
+                return Ok(vec![LiabilityLine {
+                    party: issuer.to_string(),
+                    amount: price.clone(),
+                }])
+            }
+            Apportionment::EqualSplit => vec![1; chain.len()],
+            Apportionment::Weighted(weights) => {
+                let last = *weights.last().ok_or(ApportionmentError::NoWeights)?;
+                (0..chain.len())
+                    .map(|position| weights.get(position).copied().unwrap_or(last))
+                    .collect()
+            }
+        };
+
+        let total: u128 = weights.iter().map(|weight| *weight as u128).sum();
+        if total == 0 {
+            return Err(ApportionmentError::ZeroWeights);
+        }
+
+        // Largest-remainder rounding over the exact shares `price * w / total`.
+        let magnitude = price.amount.unsigned_abs() as u128;
+        let mut shares: Vec<u128> = Vec::with_capacity(weights.len());
+        let mut remainders: Vec<(u128, usize)> = Vec::with_capacity(weights.len());
+        for (position, weight) in weights.iter().enumerate() {
+            let exact = magnitude * *weight as u128;
+            shares.push(exact / total);
+            remainders.push((exact % total, position));
+        }
+        let leftover = magnitude - shares.iter().sum::<u128>();
+        remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        for (_, position) in remainders.into_iter().take(leftover as usize) {
+            shares[position] += 1;
+        }
+
+        let sign: i128 = if price.is_negative() { -1 } else { 1 };
+        let mut lines: Vec<LiabilityLine> = Vec::new();
+        for (party, share) in chain.iter().zip(shares) {
+            let amount = (sign * share as i128) as i64;
+            match lines.iter_mut().find(|line| &line.party == party) {
+                Some(line) => line.amount.amount += amount,
+                None => lines.push(LiabilityLine {
+                    party: party.clone(),
+                    amount: price.with_amount(amount),
+                }),
+            }
+        }
+        Ok(lines)
+    }
+}
@@ -1,4 +1,10 @@
-use sha2::{Digest, Sha256};
+use crate::core::budget::Budget;
+use crate::core::canonical::{Canonical, CanonicalValue};
+use crate::core::delegation::DelegationRecord;
+use crate::core::price::Price;
+use crate::core::scope::Scope;
+use crate::core::signing::{sign_message, IssuerSignature, KeyRegistry, SignatureError};
+use ed25519_dalek::SigningKey;
 use thiserror::Error;
 
 #[derive(Debug, Error, Clone)]
@@ -6,15 +12,56 @@ pub enum AuthorityError {
     #[error("authority ID must be provided")]
     EmptyId,
     #[error("price cannot be negative: {0}")]
-    NegativePrice(i64),
+    NegativePrice(Price),
     #[error("scope must be provided")]
     EmptyScope,
+    #[error("invalid scope: {0}")]
+    InvalidScope(String),
     #[error("delegation chain must not be empty")]
     EmptyDelegationChain,
     #[error("delegation chain entries must not be empty")]
     EmptyDelegationEntry,
     #[error("timestamp must be finite and non-negative")]
     InvalidTimestamp,
+    #[error(
+        "delegation chain of {chain} principals needs {expected} delegation records, got {actual}"
+    )]
+    DelegationLengthMismatch {
+        chain: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("delegation hop {0} does not match the delegation chain")]
+    DelegationHopMismatch(usize),
+    #[error("first delegation hop is not granted by the unit's issuer '{0}'")]
+    DelegationIssuerMismatch(String),
+    #[error("delegation hop {0} widens scope beyond its parent")]
+    DelegationWidensScope(usize),
+    #[error("delegation hop {0} widens price beyond its parent")]
+    DelegationWidensPrice(usize),
+    #[error("delegation hop {0} prices in a different unit than its parent")]
+    DelegationUnitMismatch(usize),
+    #[error("invalid budget: {0}")]
+    InvalidBudget(String),
+    #[error("invalid validity window: {0}")]
+    InvalidValidityWindow(String),
+}
+
+/// Whether authority over `child` is contained in authority over `parent`.
+fn scope_within(child: &str, parent: &str) -> bool {
+    match (Scope::parse(child), Scope::parse(parent)) {
+        (Ok(child), Ok(parent)) => child.is_subset_of(&parent),
+        _ => false,
+    }
+}
+
+/// Checks that delegation hop `index` does not raise its parent's price.
+fn price_within(child: &Price, parent: &Price, index: usize) -> Result<(), AuthorityError> {
+    match child.checked_cmp(parent) {
+        Ok(std::cmp::Ordering::Greater) => Err(AuthorityError::DelegationWidensPrice(index)),
+        Ok(_) => Ok(()),
+        Err(_) => Err(AuthorityError::DelegationUnitMismatch(index)),
+    }
 }
 
 pub fn current_timestamp() -> f64 {
@@ -25,13 +72,27 @@ pub fn current_timestamp() -> f64 {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AuthorityUnit {
     pub id: String,
     pub scope: String,
     pub delegation_chain: Vec<String>,
-    pub price: i64,
+    pub price: Price,
     pub timestamp: f64,
     pub prev_hash: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub signature: Option<IssuerSignature>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub delegations: Vec<DelegationRecord>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub budget: Option<Budget>,
+    /// The unit is not valid before this instant.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub not_before: Option<f64>,
+    /// The unit is not valid from this instant on. When set, it replaces the
+    /// validator's maximum age.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub expires_at: Option<f64>,
 }
 
 impl AuthorityUnit {
@@ -39,7 +100,7 @@ impl AuthorityUnit {
         id: String,
         scope: String,
         delegation_chain: Vec<String>,
-        price: i64,
+        price: impl Into<Price>,
         timestamp: f64,
         prev_hash: Option<String>,
     ) -> Result<Self, AuthorityError> {
@@ -47,24 +108,78 @@ impl AuthorityUnit {
             id,
             scope,
             delegation_chain,
-            price,
+            price: price.into(),
             timestamp,
             prev_hash,
+            signature: None,
+            delegations: Vec::new(),
+            budget: None,
+            not_before: None,
+            expires_at: None,
         };
-        au.validate_invariants()?;
+        au.validate_fields()?;
         Ok(au)
     }
 
+    /// Attaches the signed hop records proving each link of
+    /// `delegation_chain`; record `i` grants from entry `i` to entry `i + 1`.
+    /// A unit whose chain names more than one principal does not validate
+    /// without them.
+    pub fn with_delegations(
+        mut self,
+        delegations: Vec<DelegationRecord>,
+    ) -> Result<Self, AuthorityError> {
+        self.delegations = delegations;
+        self.validate_invariants()?;
+        Ok(self)
+    }
+
+    /// Makes the unit multi-use: the gate debits `budget` on every execution
+    /// instead of burning the unit on first use.
+    pub fn with_budget(mut self, budget: Budget) -> Result<Self, AuthorityError> {
+        self.budget = Some(budget);
+        self.validate_fields()?;
+        Ok(self)
+    }
+
+    /// Restricts the unit to `[not_before, expires_at)`, independently of the
+    /// validator's maximum age.
+    pub fn with_validity(
+        mut self,
+        not_before: Option<f64>,
+        expires_at: Option<f64>,
+    ) -> Result<Self, AuthorityError> {
+        self.not_before = not_before;
+        self.expires_at = expires_at;
+        self.validate_fields()?;
+        Ok(self)
+    }
+
+    /// The budget the gate enforces; a unit without one is single-use.
+    pub fn effective_budget(&self) -> Budget {
+        self.budget.clone().unwrap_or(Budget::Uses(1))
+    }
+
     pub fn validate_invariants(&self) -> Result<(), AuthorityError> {
+        self.validate_fields()?;
+        self.validate_delegations()
+    }
+
+    /// Every invariant except the delegation records, which may be attached
+    /// after construction.
+    fn validate_fields(&self) -> Result<(), AuthorityError> {
         if self.id.is_empty() {
             return Err(AuthorityError::EmptyId);
         }
-        if self.price < 0 {
-            return Err(AuthorityError::NegativePrice(self.price));
+        if self.price.is_negative() {
+            return Err(AuthorityError::NegativePrice(self.price.clone()));
         }
         if self.scope.is_empty() {
             return Err(AuthorityError::EmptyScope);
         }
+        if let Err(err) = Scope::parse(&self.scope) {
+            return Err(AuthorityError::InvalidScope(err.to_string()));
+        }
         if self.delegation_chain.is_empty() {
             return Err(AuthorityError::EmptyDelegationChain);
         }
@@ -74,44 +189,156 @@ impl AuthorityUnit {
         if !self.timestamp.is_finite() || self.timestamp < 0.0 {
             return Err(AuthorityError::InvalidTimestamp);
         }
+        match &self.budget {
+            Some(Budget::Uses(0)) => {
+                return Err(AuthorityError::InvalidBudget(
+                    "use budget must be positive".to_string(),
+                ))
+            }
+            Some(Budget::Spend(cap))
+                if cap.amount <= 0 || !cap.checked_cmp(&self.price).is_ok_and(|o| o.is_ge()) =>
+            {
+                return Err(AuthorityError::InvalidBudget(format!(
+                    "spend cap {} must be positive and cover the unit price {}",
+                    cap, self.price
+                )))
+            }
+            Some(Budget::Spend(_)) if self.price.amount <= 0 => {
+                return Err(AuthorityError::InvalidBudget(format!(
+                    "a spend budget needs a positive unit price, not {}",
+                    self.price
+                )))
+            }
+            _ => {}
+        }
+        self.validate_window()
+    }
+
+    fn validate_window(&self) -> Result<(), AuthorityError> {
+        let invalid = |reason: &str| Err(AuthorityError::InvalidValidityWindow(reason.to_string()));
+        for bound in [self.not_before, self.expires_at].into_iter().flatten() {
+            if !bound.is_finite() || bound < 0.0 {
+                return invalid("bounds must be finite and non-negative");
+            }
+        }
+        if let Some(expires_at) = self.expires_at {
+            if expires_at <= self.timestamp {
+                return invalid("expires_at must be after the unit's timestamp");
+            }
+            if self
+                .not_before
+                .is_some_and(|not_before| expires_at <= not_before)
+            {
+                return invalid("expires_at must be after not_before");
+            }
+        }
         Ok(())
     }
 
+    /// Structural checks on the delegation records: there must be one per
+    /// hop, the first granted by the unit's issuer, and every hop must link
+    /// consecutive chain entries and may only narrow its parent's scope and
+    /// price. The unit itself counts as the final hop. Signatures and the
+    /// issuer's root authority are checked separately, by
+    /// `verify_delegations` and `validate_root`.
+    fn validate_delegations(&self) -> Result<(), AuthorityError> {
+        let expected = self.delegation_chain.len() - 1;
+        if self.delegations.len() != expected {
+            return Err(AuthorityError::DelegationLengthMismatch {
+                chain: self.delegation_chain.len(),
+                expected,
+                actual: self.delegations.len(),
+            });
+        }
+        if let Some(first) = self.delegations.first() {
+            if first.delegator != self.issuer() {
+                return Err(AuthorityError::DelegationIssuerMismatch(
+                    self.issuer().to_string(),
+                ));
+            }
+        }
+        let mut parent: Option<&DelegationRecord> = None;
+        for (index, record) in self.delegations.iter().enumerate() {
+            if record.delegator != self.delegation_chain[index]
+                || record.delegatee != self.delegation_chain[index + 1]
+            {
+                return Err(AuthorityError::DelegationHopMismatch(index));
+            }
+            if let Some(parent) = parent {
+                if !scope_within(&record.scope, &parent.scope) {
+                    return Err(AuthorityError::DelegationWidensScope(index));
+                }
+                price_within(&record.price, &parent.price, index)?;
+            }
+            parent = Some(record);
+        }
+        if let Some(last) = parent {
+            if !scope_within(&self.scope, &last.scope) {
+                return Err(AuthorityError::DelegationWidensScope(expected));
+            }
+            price_within(&self.price, &last.price, expected)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the first hop against the scope and price the issuer holds.
+    /// A unit without delegations is bounded by its own scope and price.
+    pub fn validate_root(&self, scope: &str, price: &Price) -> Result<(), AuthorityError> {
+        let (first_scope, first_price) = match self.delegations.first() {
+            Some(first) => (&first.scope, &first.price),
+            None => (&self.scope, &self.price),
+        };
+        if !scope_within(first_scope, scope) {
+            return Err(AuthorityError::DelegationWidensScope(0));
+        }
+        price_within(first_price, price, 0)
+    }
+
+    pub fn verify_delegations(&self, delegators: &KeyRegistry) -> Result<(), SignatureError> {
+        let mut parent = None;
+        for record in &self.delegations {
+            record.verify(&self.id, parent, delegators)?;
+            parent = Some(record);
+        }
+        Ok(())
+    }
+
+    /// SHA-256 of the unit's canonical JSON. The issuer signature is not
+    /// covered, since it is computed over this digest.
     pub fn hash(&self) -> String {
-        let mut hasher = Sha256::new();
-
-        fn update_part(hasher: &mut Sha256, label: &str, value: &str) {
-            hasher.update(label.as_bytes());
-            hasher.update(b":");
-            hasher.update(value.len().to_string().as_bytes());
-            hasher.update(b":");
-            hasher.update(value.as_bytes());
-            hasher.update(b";");
-        }
-
-        update_part(&mut hasher, "id", &self.id);
-        update_part(&mut hasher, "scope", &self.scope);
-        update_part(
-            &mut hasher,
-            "delegation_len",
-            &self.delegation_chain.len().to_string(),
-        );
-        for entry in &self.delegation_chain {
-            update_part(&mut hasher, "delegation", entry);
-        }
-        update_part(&mut hasher, "price", &self.price.to_string());
-        update_part(
-            &mut hasher,
-            "timestamp",
-            &self.timestamp.to_bits().to_string(),
-        );
-        update_part(
-            &mut hasher,
-            "prev_hash",
-            self.prev_hash.as_deref().unwrap_or(""),
-        );
-        let result = hasher.finalize();
-        format!("{:x}", result)
+        self.canonical_value().sha256_hex()
+    }
+
+    /// The principal accountable for minting this unit: the signing issuer
+    /// when present, otherwise the root of the delegation chain.
+    pub fn issuer(&self) -> &str {
+        match &self.signature {
+            Some(signature) => &signature.issuer,
+            None => self
+                .delegation_chain
+                .first()
+                .map(String::as_str)
+                .unwrap_or(""),
+        }
+    }
+
+    pub fn sign(&mut self, issuer: &str, key: &SigningKey) {
+        self.signature = Some(IssuerSignature {
+            issuer: issuer.to_string(),
+            signature: sign_message(key, self.hash().as_bytes()),
+        });
+    }
+
+    pub fn verify_signature(&self, trusted: &KeyRegistry) -> Result<(), SignatureError> {
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or(SignatureError::MissingSignature)?;
+        trusted.verify(
+            &signature.issuer,
+            self.hash().as_bytes(),
+            &signature.signature,
+        )
     }
 
     pub fn is_valid(&self, current_time: f64, max_age_seconds: i64) -> bool {
@@ -122,13 +349,72 @@ impl AuthorityUnit {
         {
             return false;
         }
-        if current_time - self.timestamp > max_age_seconds as f64 {
+        if self
+            .not_before
+            .is_some_and(|not_before| current_time < not_before)
+        {
             return false;
         }
-        true
+        match self.expires_at {
+            Some(expires_at) => current_time < expires_at,
+            None => current_time - self.timestamp <= max_age_seconds as f64,
+        }
     }
 
     pub fn can_consume(&self, action_scope: &str) -> bool {
-        self.scope == action_scope || self.scope == "any"
+        scope_within(action_scope, &self.scope)
+    }
+}
+
+/// The signed content of the unit: every field except `signature`.
+impl Canonical for AuthorityUnit {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("id", CanonicalValue::str(&self.id)),
+            ("scope", CanonicalValue::str(&self.scope)),
+            (
+                "delegation_chain",
+                CanonicalValue::Array(
+                    self.delegation_chain
+                        .iter()
+                        .map(|entry| CanonicalValue::str(entry))
+                        .collect(),
+                ),
+            ),
+            (
+                "delegations",
+                CanonicalValue::Array(
+                    self.delegations
+                        .iter()
+                        .map(Canonical::canonical_value)
+                        .collect(),
+                ),
+            ),
+            ("price", self.price.canonical_value()),
+            ("timestamp", CanonicalValue::Float(self.timestamp)),
+            (
+                "prev_hash",
+                CanonicalValue::opt_str(self.prev_hash.as_deref()),
+            ),
+            (
+                "budget",
+                self.budget
+                    .as_ref()
+                    .map(Canonical::canonical_value)
+                    .unwrap_or(CanonicalValue::Null),
+            ),
+            (
+                "not_before",
+                self.not_before
+                    .map(CanonicalValue::Float)
+                    .unwrap_or(CanonicalValue::Null),
+            ),
+            (
+                "expires_at",
+                self.expires_at
+                    .map(CanonicalValue::Float)
+                    .unwrap_or(CanonicalValue::Null),
+            ),
+        ])
     }
 }
@@ -0,0 +1,101 @@
+use crate::core::canonical::{Canonical, CanonicalValue};
+use crate::core::price::Price;
+
+/// How much a multi-use authority unit may be exercised before it is
+/// exhausted: a number of executions, or a cap on cumulative spend where each
+/// execution is charged the unit's price. A spend cap is in the unit of the
+/// unit's price.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Budget {
+    Uses(u64),
+    Spend(Price),
+}
+
+/// Cumulative consumption recorded against one authority unit. Zero spend
+/// recorded without a unit takes the unit of the next amount applied to it;
+/// non-zero unitless spend only combines with unitless amounts.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Usage {
+    pub uses: u64,
+    pub spent: Price,
+}
+
+impl Usage {
+    /// The usage after one more execution charged `amount`, or `None` if
+    /// either count would overflow or the units differ.
+    pub fn debited(&self, amount: &Price) -> Option<Usage> {
+        Some(Usage {
+            uses: self.uses.checked_add(1)?,
+            spent: self.spent_in(amount)?.checked_add(amount).ok()?,
+        })
+    }
+
+    pub fn credited(&self, amount: &Price) -> Option<Usage> {
+        Some(Usage {
+            uses: self.uses.saturating_sub(1),
+            spent: self.spent_in(amount)?.checked_sub(amount).ok()?,
+        })
+    }
+
+    /// Returns part of an execution's charge without reversing the execution.
+    pub fn refunded(&self, amount: &Price) -> Option<Usage> {
+        Some(Usage {
+            uses: self.uses,
+            spent: self.spent_in(amount)?.checked_sub(amount).ok()?,
+        })
+    }
+
+    /// The spend in `other`'s unit, if it is zero or already in that unit.
+    fn spent_in(&self, other: &Price) -> Option<Price> {
+        if self.spent.unit == other.unit {
+            Some(self.spent.clone())
+        } else if self.spent.unit.is_empty() && self.spent.amount == 0 {
+            Some(other.with_amount(0))
+        } else {
+            None
+        }
+    }
+}
+
+impl Budget {
+    /// Whether one more execution charged `amount` fits after `usage`.
+    pub fn admits(&self, usage: &Usage, amount: &Price) -> bool {
+        self.debit(usage, amount).is_some()
+    }
+
+    /// The usage after one more execution charged `amount`, or `None` if it
+    /// does not fit or its usage would overflow.
+    pub fn debit(&self, usage: &Usage, amount: &Price) -> Option<Usage> {
+        let debited = usage.debited(amount)?;
+        let fits = match self {
+            Budget::Uses(max) => usage.uses < *max,
+            Budget::Spend(cap) => debited.spent.checked_cmp(cap).ok()?.is_le(),
+        };
+        fits.then_some(debited)
+    }
+
+    /// The budget left after `usage`, in the same unit as `self`.
+    pub fn remaining(&self, usage: &Usage) -> Budget {
+        match self {
+            Budget::Uses(max) => Budget::Uses(max.saturating_sub(usage.uses)),
+            Budget::Spend(cap) => {
+                // Spend in another unit cannot be set against the cap.
+                let left = usage
+                    .spent_in(cap)
+                    .map_or(0, |spent| cap.amount.saturating_sub(spent.amount));
+                Budget::Spend(cap.with_amount(left.max(0)))
+            }
+        }
+    }
+}
+
+impl Canonical for Budget {
+    fn canonical_value(&self) -> CanonicalValue {
+        match self {
+            Budget::Uses(uses) => CanonicalValue::object([("uses", CanonicalValue::UInt(*uses))]),
+            Budget::Spend(cap) => CanonicalValue::object([("spend", cap.canonical_value())]),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Canonical JSON encoding shared by every hashed type.
+//!
+//! The encoding is the exact byte sequence covered by `hash()`:
+//!
+//! - objects have their keys sorted by byte value, with no whitespace;
+//! - strings escape only `"`, `\` and control characters, using `\b`, `\f`,
+//!   `\n`, `\r`, `\t` where available and lowercase `\u00xx` otherwise;
+//! - integers are plain decimal;
+//! - floats use the shortest decimal that round-trips, never an exponent,
+//!   with no fractional part when the value is integral; non-finite floats
+//!   encode as `null`;
+//! - absent optional values encode as `null`.
+//!
+//! Key names match the struct field names, so the canonical form of a type
+//! deserializes with the `serde` feature.
+
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanonicalValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Array(Vec<CanonicalValue>),
+    Object(Vec<(String, CanonicalValue)>),
+}
+
+impl CanonicalValue {
+    pub fn object<const N: usize>(fields: [(&str, CanonicalValue); N]) -> Self {
+        CanonicalValue::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn str(value: &str) -> Self {
+        CanonicalValue::Str(value.to_string())
+    }
+
+    pub fn opt_str(value: Option<&str>) -> Self {
+        value
+            .map(CanonicalValue::str)
+            .unwrap_or(CanonicalValue::Null)
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out);
+        out
+    }
+
+    pub fn sha256_hex(&self) -> String {
+        format!("{:x}", Sha256::digest(self.to_json().as_bytes()))
+    }
+
+    fn write(&self, out: &mut String) {
+        match self {
+            CanonicalValue::Null => out.push_str("null"),
+            CanonicalValue::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            CanonicalValue::Int(value) => out.push_str(&value.to_string()),
+            CanonicalValue::UInt(value) => out.push_str(&value.to_string()),
+            CanonicalValue::Float(value) if value.is_finite() => out.push_str(&value.to_string()),
+            CanonicalValue::Float(_) => out.push_str("null"),
+            CanonicalValue::Str(value) => write_string(value, out),
+            CanonicalValue::Array(items) => {
+                out.push('[');
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    item.write(out);
+                }
+                out.push(']');
+            }
+            CanonicalValue::Object(fields) => {
+                let mut sorted: Vec<&(String, CanonicalValue)> = fields.iter().collect();
+                sorted.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+                out.push('{');
+                for (index, (key, value)) in sorted.into_iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    write_string(key, out);
+                    out.push(':');
+                    value.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Types with a canonical JSON form.
+pub trait Canonical {
+    fn canonical_value(&self) -> CanonicalValue;
+
+    fn canonical_json(&self) -> String {
+        self.canonical_value().to_json()
+    }
+}
@@ -0,0 +1,61 @@
+use crate::core::authority::current_timestamp;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Source of the current time, in seconds since the UNIX epoch. Everything
+/// that validates or stamps records reads time through a `Clock`, so that
+/// identical inputs under a fixed clock produce identical outcomes.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> f64;
+}
+
+/// Wall-clock time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        current_timestamp()
+    }
+}
+
+/// Always reports the same instant.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub f64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> f64 {
+        self.0
+    }
+}
+
+/// Reports an instant that only changes when it is set or advanced.
+#[derive(Debug)]
+pub struct ManualClock {
+    bits: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(start: f64) -> Self {
+        ManualClock {
+            bits: AtomicU64::new(start.to_bits()),
+        }
+    }
+
+    pub fn set(&self, now: f64) {
+        self.bits.store(now.to_bits(), Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        let _ = self
+            .bits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bits| {
+                Some((f64::from_bits(bits) + seconds).to_bits())
+            });
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::SeqCst))
+    }
+}
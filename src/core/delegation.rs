@@ -0,0 +1,79 @@
+use crate::core::canonical::{Canonical, CanonicalValue};
+use crate::core::price::Price;
+use crate::core::signing::{sign_message, KeyRegistry, SignatureError};
+use ed25519_dalek::SigningKey;
+
+/// One signed hop of a delegation chain: `delegator` grants `delegatee` at most
+/// `scope` and `price`. The signature binds the hop to the authority unit and
+/// to the hop that precedes it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DelegationRecord {
+    pub delegator: String,
+    pub delegatee: String,
+    pub scope: String,
+    pub price: Price,
+    pub signature: String,
+}
+
+impl DelegationRecord {
+    pub fn signed(
+        authority_id: &str,
+        parent: Option<&DelegationRecord>,
+        delegator: &str,
+        delegatee: &str,
+        scope: &str,
+        price: impl Into<Price>,
+        key: &SigningKey,
+    ) -> Self {
+        let mut record = DelegationRecord {
+            delegator: delegator.to_string(),
+            delegatee: delegatee.to_string(),
+            scope: scope.to_string(),
+            price: price.into(),
+            signature: String::new(),
+        };
+        let digest = record.digest(authority_id, parent);
+        record.signature = sign_message(key, digest.as_bytes());
+        record
+    }
+
+    /// Digest signed by the delegator. The parent's signature is folded in so
+    /// hops cannot be reordered or spliced between chains.
+    pub fn digest(&self, authority_id: &str, parent: Option<&DelegationRecord>) -> String {
+        CanonicalValue::object([
+            ("authority_id", CanonicalValue::str(authority_id)),
+            (
+                "parent",
+                CanonicalValue::opt_str(parent.map(|p| p.signature.as_str())),
+            ),
+            ("delegator", CanonicalValue::str(&self.delegator)),
+            ("delegatee", CanonicalValue::str(&self.delegatee)),
+            ("scope", CanonicalValue::str(&self.scope)),
+            ("price", self.price.canonical_value()),
+        ])
+        .sha256_hex()
+    }
+
+    pub fn verify(
+        &self,
+        authority_id: &str,
+        parent: Option<&DelegationRecord>,
+        delegators: &KeyRegistry,
+    ) -> Result<(), SignatureError> {
+        let digest = self.digest(authority_id, parent);
+        delegators.verify(&self.delegator, digest.as_bytes(), &self.signature)
+    }
+}
+
+impl Canonical for DelegationRecord {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("delegator", CanonicalValue::str(&self.delegator)),
+            ("delegatee", CanonicalValue::str(&self.delegatee)),
+            ("scope", CanonicalValue::str(&self.scope)),
+            ("price", self.price.canonical_value()),
+            ("signature", CanonicalValue::str(&self.signature)),
+        ])
+    }
+}
@@ -1,8 +1,21 @@
+use crate::core::apportionment::{Apportionment, LiabilityLine};
 use crate::core::authority::AuthorityUnit;
-use crate::core::trace::{DecisionTrace, LiabilityRecord};
-use std::collections::HashSet;
+use crate::core::budget::{Budget, Usage};
+use crate::core::clock::{Clock, SystemClock};
+use crate::core::policy::{PolicyChain, PolicyContext, RuleEvaluation};
+use crate::core::price::Price;
+use crate::core::pricing::{bounded_charge, Pricer, PricingContext};
+use crate::core::ratelimit::{RateLimitError, RateLimitKey, RateLimiter};
+use crate::core::sink::{ChainPosition, MemoryTraceSink, SinkError, TraceSink};
+use crate::core::store::{ConsumptionStore, MemoryConsumptionStore, ReservationHold, StoreError};
+use crate::core::trace::{DecisionTrace, IdStrategy, LiabilityRecord, TraceOutcome, TraceSummary};
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Display;
 use std::sync::{Arc, Mutex};
 use thiserror::Error;
+use uuid::Uuid;
 
 #[derive(Debug, Error, Clone)]
 pub enum ExecutionGateError {
@@ -10,6 +23,8 @@ pub enum ExecutionGateError {
     InvalidAuthority(String),
     #[error("authority unit already consumed: {0}")]
     AlreadyConsumed(String),
+    #[error("authority unit budget exhausted: {0}")]
+    BudgetExhausted(String),
     #[error("authority scope '{authority_scope}' cannot perform action scope '{action_scope}'")]
     ScopeMismatch {
         authority_scope: String,
@@ -17,29 +32,363 @@ pub enum ExecutionGateError {
     },
     #[error("action execution failed: {0}")]
     ActionFailed(String),
+    #[error("consumption store error: {0}")]
+    StoreError(String),
+    #[error("trace sink error: {0}")]
+    SinkFailed(String),
+    #[error("liability apportionment failed: {0}")]
+    ApportionmentFailed(String),
+    #[error("pricing failed: {0}")]
+    PricingFailed(String),
+    #[error("execution policy '{rule}' denied: {reason}")]
+    PolicyDenied { rule: String, reason: String },
+    #[error("rate limit for {key} exceeded, retry after {retry_after:.3}s")]
+    RateLimited { key: RateLimitKey, retry_after: f64 },
+    #[error("reservation {0} expired before it was settled")]
+    ReservationExpired(String),
+    #[error("reservation {0} was recovered after a restart and can only be aborted")]
+    ReservationRecovered(String),
+    #[error("reservation {0} was already settled")]
+    ReservationSettled(String),
+    #[error("no reservation {0} is held")]
+    UnknownReservation(String),
+    #[error("reservation timeout must be finite and positive, got {0}")]
+    InvalidReservationTimeout(f64),
+    #[error("compensation failed: {0}")]
+    CompensationFailed(String),
     #[error("internal lock error")]
     LockError,
 }
 
+impl ExecutionGateError {
+    /// Stable identifier recorded as `DecisionTrace::error_kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ExecutionGateError::InvalidAuthority(_) => "invalid_authority",
+            ExecutionGateError::AlreadyConsumed(_) => "already_consumed",
+            ExecutionGateError::BudgetExhausted(_) => "budget_exhausted",
+            ExecutionGateError::ScopeMismatch { .. } => "scope_mismatch",
+            ExecutionGateError::ActionFailed(_) => "action_failed",
+            ExecutionGateError::StoreError(_) => "store_error",
+            ExecutionGateError::SinkFailed(_) => "sink_failed",
+            ExecutionGateError::ApportionmentFailed(_) => "apportionment_failed",
+            ExecutionGateError::PricingFailed(_) => "pricing_failed",
+            ExecutionGateError::PolicyDenied { .. } => "policy_denied",
+            ExecutionGateError::RateLimited { .. } => "rate_limited",
+            ExecutionGateError::ReservationExpired(_) => "reservation_expired",
+            ExecutionGateError::ReservationRecovered(_) => "reservation_recovered",
+            ExecutionGateError::ReservationSettled(_) => "reservation_settled",
+            ExecutionGateError::UnknownReservation(_) => "unknown_reservation",
+            ExecutionGateError::InvalidReservationTimeout(_) => "invalid_reservation_timeout",
+            ExecutionGateError::CompensationFailed(_) => "compensation_failed",
+            ExecutionGateError::LockError => "lock_error",
+        }
+    }
+}
+
+/// Failure of a typed execution: either the gate refused or rolled back the
+/// action, or the action itself returned its own error.
+#[derive(Debug, Error)]
+pub enum ActionError<E> {
+    #[error(transparent)]
+    Gate(ExecutionGateError),
+    #[error("action execution failed: {0}")]
+    Action(E),
+}
+
+impl<E> From<ExecutionGateError> for ActionError<E> {
+    fn from(err: ExecutionGateError) -> Self {
+        ActionError::Gate(err)
+    }
+}
+
+impl<E: Display> From<ActionError<E>> for ExecutionGateError {
+    fn from(err: ActionError<E>) -> Self {
+        match err {
+            ActionError::Gate(err) => err,
+            ActionError::Action(err) => ExecutionGateError::ActionFailed(err.to_string()),
+        }
+    }
+}
+
+impl From<SinkError> for ExecutionGateError {
+    fn from(err: SinkError) -> Self {
+        match err {
+            SinkError::LockError => ExecutionGateError::LockError,
+            other => ExecutionGateError::SinkFailed(other.to_string()),
+        }
+    }
+}
+
+impl From<StoreError> for ExecutionGateError {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::LockError => ExecutionGateError::LockError,
+            other => ExecutionGateError::StoreError(other.to_string()),
+        }
+    }
+}
+
+/// Undoes the effects of an action that ran but was not committed. It is
+/// given the failure and returns a summary of what it undid. It is `Sync`
+/// so that async executions can be spawned onto other threads.
+pub type Compensation<'a> = &'a (dyn Fn(&str) -> Result<String, String> + Sync);
+
+/// A compensation the gate keeps with a reservation until it is settled.
+pub type ReservationCompensation = Arc<dyn Fn(&str) -> Result<String, String> + Send + Sync>;
+
+/// The request being decided, threaded through admission and settlement.
+struct Attempt<'a> {
+    au: &'a AuthorityUnit,
+    action_name: &'a str,
+    action_scope: &'a str,
+    compensation: Option<Compensation<'a>>,
+}
+
+/// What admission granted: the usage after the debit, how the price of the
+/// execution is apportioned, and the policy evaluation that allowed it at
+/// `admitted_at`. Usage and line items are revised if a pricer charges less
+/// than the unit's price.
+struct Admission {
+    usage: Usage,
+    line_items: Vec<LiabilityLine>,
+    policy: Vec<RuleEvaluation>,
+    admitted_at: f64,
+}
+
+/// An admitted attempt awaiting the outcome of an action run outside the
+/// gate.
+struct PendingReservation {
+    au: AuthorityUnit,
+    action_name: String,
+    action_scope: String,
+    admission: Admission,
+    compensation: Option<ReservationCompensation>,
+    expires_at: f64,
+}
+
+/// How a reservation that is no longer held was closed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Closure {
+    Settled,
+    Expired,
+}
+
+/// A debit held for an action that runs outside the gate, e.g. on a remote
+/// worker. Settle it exactly once with `commit` or `abort`, or by ID through
+/// the gate; if neither happens before `expires_at`, the gate releases the
+/// debit and records the expiry as a failed trace.
+pub struct Reservation<'a, F: Fn(&AuthorityUnit) -> bool + Send + Sync> {
+    gate: &'a ExecutionGate<F>,
+    pub id: String,
+    pub authority_id: String,
+    pub expires_at: f64,
+}
+
+impl<F: Fn(&AuthorityUnit) -> bool + Send + Sync> Reservation<'_, F> {
+    /// Records the action's output and emits the trace and liability record,
+    /// exactly as a successful closure would.
+    pub fn commit<T: TraceSummary>(
+        self,
+        output: T,
+    ) -> Result<(DecisionTrace, LiabilityRecord), ExecutionGateError> {
+        self.gate.commit_reservation(&self.id, output)
+    }
+
+    /// Refunds the debit and records a failed trace carrying `reason`, as
+    /// for an action that returned an error.
+    pub fn abort(self, reason: &str) -> Result<(), ExecutionGateError> {
+        self.gate.abort_reservation(&self.id, reason)
+    }
+}
+
 pub struct ExecutionGate<F: Fn(&AuthorityUnit) -> bool + Send + Sync> {
     validator: F,
-    consumed_au_ids: Arc<Mutex<HashSet<String>>>,
+    consumed: Arc<dyn ConsumptionStore>,
+    sink: Arc<dyn TraceSink>,
+    chain: Mutex<ChainPosition>,
+    clock: Arc<dyn Clock>,
+    id_strategy: IdStrategy,
+    apportionment: Apportionment,
+    pricer: Option<Arc<dyn Pricer>>,
+    policy: Option<Arc<PolicyChain>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    reservations: Mutex<HashMap<String, PendingReservation>>,
+    /// Recently closed reservations, with when to forget them, so a late
+    /// settlement is told what became of its reservation.
+    closed: Mutex<HashMap<String, (Closure, f64)>>,
+    reservation_timeout: f64,
 }
 
 impl<F: Fn(&AuthorityUnit) -> bool + Send + Sync> ExecutionGate<F> {
     pub fn new(validator: F) -> Self {
         ExecutionGate {
             validator,
-            consumed_au_ids: Arc::new(Mutex::new(HashSet::new())),
+            consumed: Arc::new(MemoryConsumptionStore::new()),
+            sink: Arc::new(MemoryTraceSink::new()),
+            chain: Mutex::new(ChainPosition::default()),
+            clock: Arc::new(SystemClock),
+            id_strategy: IdStrategy::Random,
+            apportionment: Apportionment::IssuerTakesAll,
+            pricer: None,
+            policy: None,
+            rate_limiter: None,
+            reservations: Mutex::new(HashMap::new()),
+            closed: Mutex::new(HashMap::new()),
+            reservation_timeout: 300.0,
         }
     }
 
+    /// Chooses how trace and liability IDs are assigned. With
+    /// `IdStrategy::ContentDerived`, replaying the same executions yields the
+    /// same IDs.
+    pub fn with_id_strategy(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    /// Chooses how each liability record's price is divided among the
+    /// unit's delegation chain. The issuer takes all by default.
+    pub fn with_apportionment(mut self, apportionment: Apportionment) -> Self {
+        self.apportionment = apportionment;
+        self
+    }
+
+    /// Prices each execution after its action completes. The unit's price is
+    /// reserved up front as the quote and caps the charge; any difference is
+    /// returned to the unit's budget.
+    pub fn with_pricer(mut self, pricer: Arc<dyn Pricer>) -> Self {
+        self.pricer = Some(pricer);
+        self
+    }
+
+    /// Evaluates `policy` before each debit. A denial is returned as
+    /// `PolicyDenied`, and every trace carries the full evaluation. The chain
+    /// is shared, so its rules can be changed while the gate runs.
+    pub fn with_policy(mut self, policy: Arc<PolicyChain>) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Takes a token from each of `limiter`'s applicable buckets once the
+    /// policy chain allows an execution and before the unit is debited,
+    /// refusing with `RateLimited` when one is empty. An execution that is
+    /// rolled back gives its tokens back. The
+    /// limiter is shared, so its limits can be adjusted while the gate runs.
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// How long a reservation may stay unsettled before its debit is
+    /// released. Five minutes by default.
+    pub fn with_reservation_timeout(mut self, seconds: f64) -> Result<Self, ExecutionGateError> {
+        if !seconds.is_finite() || seconds <= 0.0 {
+            return Err(ExecutionGateError::InvalidReservationTimeout(seconds));
+        }
+        self.reservation_timeout = seconds;
+        Ok(self)
+    }
+
+    /// Stamps traces and liability records from `clock` instead of the
+    /// system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sends every trace to `sink`, continuing the hash chain from wherever
+    /// the sink left off. The default sink only keeps traces in memory.
+    pub fn with_sink(mut self, sink: Arc<dyn TraceSink>) -> Result<Self, ExecutionGateError> {
+        self.chain = Mutex::new(sink.resume()?);
+        self.sink = sink;
+        Ok(self)
+    }
+
+    /// Replaces the default in-memory store, e.g. with a
+    /// `FileConsumptionStore` so spent units stay spent across restarts.
+    pub fn with_store(mut self, store: Arc<dyn ConsumptionStore>) -> Self {
+        self.consumed = store;
+        self
+    }
+
+    /// Whether any execution has been recorded against the unit.
     pub fn is_consumed(&self, au_id: &str) -> Result<bool, ExecutionGateError> {
-        let guard = self
-            .consumed_au_ids
+        Ok(self.consumed.is_consumed(au_id)?)
+    }
+
+    /// Hash of the last trace this gate emitted, for anchoring the chain.
+    pub fn trace_head(&self) -> Result<Option<String>, ExecutionGateError> {
+        self.chain
+            .lock()
+            .map(|chain| chain.head.clone())
+            .map_err(|_| ExecutionGateError::LockError)
+    }
+
+    /// Links `trace` into the chain, assigns content-derived IDs if
+    /// configured, and writes it to the sink. The chain only advances once the
+    /// sink has accepted the write.
+    fn record(
+        &self,
+        attempt: &Attempt,
+        trace: &mut DecisionTrace,
+        liability: Option<&mut LiabilityRecord>,
+    ) -> Result<(), ExecutionGateError> {
+        self.record_as(
+            &attempt.au.hash(),
+            attempt.action_name,
+            attempt.action_scope,
+            trace,
+            liability,
+        )
+    }
+
+    /// `record` for a trace whose unit is only known by its hash, such as
+    /// that of a hold recovered from the store.
+    fn record_as(
+        &self,
+        au_hash: &str,
+        action_name: &str,
+        action_scope: &str,
+        trace: &mut DecisionTrace,
+        mut liability: Option<&mut LiabilityRecord>,
+    ) -> Result<(), ExecutionGateError> {
+        let mut chain = self
+            .chain
             .lock()
             .map_err(|_| ExecutionGateError::LockError)?;
-        Ok(guard.contains(au_id))
+        trace.sequence = chain.next_sequence;
+        trace.prev_hash = chain.head.clone();
+        if self.id_strategy == IdStrategy::ContentDerived {
+            trace.id = DecisionTrace::derive_id(
+                au_hash,
+                action_name,
+                action_scope,
+                &trace.result,
+                trace.sequence,
+            );
+            if let Some(liability) = liability.as_mut() {
+                liability.trace_id = trace.id.clone();
+                liability.id = LiabilityRecord::derive_id(&trace.id);
+            }
+        }
+        self.sink.write(trace, liability.as_deref())?;
+        chain.next_sequence += 1;
+        chain.head = Some(trace.hash());
+        Ok(())
+    }
+
+    /// The budget still available to `au`.
+    pub fn remaining(&self, au: &AuthorityUnit) -> Result<Budget, ExecutionGateError> {
+        let usage = self.consumed.usage(&au.id)?;
+        Ok(au.effective_budget().remaining(&usage))
+    }
+
+    fn exhausted(au: &AuthorityUnit) -> ExecutionGateError {
+        match au.budget {
+            Some(_) => ExecutionGateError::BudgetExhausted(au.id.clone()),
+            None => ExecutionGateError::AlreadyConsumed(au.id.clone()),
+        }
     }
 
     pub fn execute_with_authority(
@@ -49,17 +398,548 @@ impl<F: Fn(&AuthorityUnit) -> bool + Send + Sync> ExecutionGate<F> {
         action_name: &str,
         action_scope: &str,
     ) -> Result<(DecisionTrace, LiabilityRecord), ExecutionGateError> {
-        if !(self.validator)(au) {
-            return Err(ExecutionGateError::InvalidAuthority(au.id.clone()));
+        let (_, dt, lr) = self.execute_typed(au, action_fn, action_name, action_scope)?;
+        Ok((dt, lr))
+    }
+
+    /// Runs an action with typed output and error. The output is summarized
+    /// into the trace through `TraceSummary` and handed back to the caller
+    /// alongside the trace and liability record.
+    pub fn execute_typed<T: TraceSummary, E: Display>(
+        &self,
+        au: &AuthorityUnit,
+        action_fn: &dyn Fn() -> Result<T, E>,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<(T, DecisionTrace, LiabilityRecord), ActionError<E>> {
+        let attempt = Attempt {
+            au,
+            action_name,
+            action_scope,
+            compensation: None,
+        };
+        let admission = self.admit(&attempt)?;
+        self.settle(&attempt, admission, action_fn())
+    }
+
+    /// Like `execute_with_authority`, but runs `compensation` whenever the
+    /// action ran and the execution was rolled back: when the action failed,
+    /// or when pricing refused it afterwards. The compensation runs before
+    /// the debit is refunded, and its outcome is recorded in its own trace.
+    pub fn execute_with_compensation(
+        &self,
+        au: &AuthorityUnit,
+        action_fn: &dyn Fn() -> Result<String, String>,
+        compensation: Compensation,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<(DecisionTrace, LiabilityRecord), ExecutionGateError> {
+        let (_, dt, lr) = self.execute_typed_with_compensation(
+            au,
+            action_fn,
+            compensation,
+            action_name,
+            action_scope,
+        )?;
+        Ok((dt, lr))
+    }
+
+    pub fn execute_typed_with_compensation<T: TraceSummary, E: Display>(
+        &self,
+        au: &AuthorityUnit,
+        action_fn: &dyn Fn() -> Result<T, E>,
+        compensation: Compensation,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<(T, DecisionTrace, LiabilityRecord), ActionError<E>> {
+        let attempt = Attempt {
+            au,
+            action_name,
+            action_scope,
+            compensation: Some(compensation),
+        };
+        let admission = self.admit(&attempt)?;
+        self.settle(&attempt, admission, action_fn())
+    }
+
+    /// First phase of an execution whose action runs outside the gate. The
+    /// unit is admitted and debited exactly as for `execute_with_authority`,
+    /// and the returned reservation holds the debit until it is committed,
+    /// aborted or expires. The hold is recorded in the consumption store, so
+    /// a gate restarted on a durable store still releases it at its
+    /// deadline.
+    pub fn reserve(
+        &self,
+        au: &AuthorityUnit,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<Reservation<'_, F>, ExecutionGateError> {
+        self.reserve_attempt(
+            Uuid::new_v4().to_string(),
+            au,
+            None,
+            action_name,
+            action_scope,
+        )
+    }
+
+    /// Like `reserve`, but runs `compensation` if the reservation is aborted
+    /// or expires. A hold recovered after a restart is released without it.
+    pub fn reserve_with_compensation(
+        &self,
+        au: &AuthorityUnit,
+        compensation: ReservationCompensation,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<Reservation<'_, F>, ExecutionGateError> {
+        self.reserve_attempt(
+            Uuid::new_v4().to_string(),
+            au,
+            Some(compensation),
+            action_name,
+            action_scope,
+        )
+    }
+
+    /// Reserves under a given ID, so a replay reproduces the recorded
+    /// reservation IDs that its traces carry.
+    pub(crate) fn reserve_as(
+        &self,
+        id: &str,
+        au: &AuthorityUnit,
+        compensation: Option<ReservationCompensation>,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<Reservation<'_, F>, ExecutionGateError> {
+        self.reserve_attempt(id.to_string(), au, compensation, action_name, action_scope)
+    }
+
+    fn reserve_attempt(
+        &self,
+        id: String,
+        au: &AuthorityUnit,
+        compensation: Option<ReservationCompensation>,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<Reservation<'_, F>, ExecutionGateError> {
+        let attempt = Attempt {
+            au,
+            action_name,
+            action_scope,
+            compensation: None,
+        };
+        let admission = self.admit(&attempt)?;
+        let reservation = Reservation {
+            gate: self,
+            id,
+            authority_id: au.id.clone(),
+            expires_at: self.clock.now() + self.reservation_timeout,
+        };
+        let hold = ReservationHold {
+            reservation_id: reservation.id.clone(),
+            authority_id: au.id.clone(),
+            authority_hash: au.hash(),
+            action_name: action_name.to_string(),
+            action_scope: action_scope.to_string(),
+            amount: au.price.clone(),
+            expires_at: reservation.expires_at,
+        };
+        if let Err(err) = self.consumed.hold(&hold) {
+            let err = ExecutionGateError::from(err);
+            self.fail(&attempt, admission, err.kind(), err.to_string())?;
+            return Err(err);
+        }
+        let pending = PendingReservation {
+            au: au.clone(),
+            action_name: action_name.to_string(),
+            action_scope: action_scope.to_string(),
+            admission,
+            compensation,
+            expires_at: reservation.expires_at,
+        };
+        self.reservations
+            .lock()
+            .map_err(|_| ExecutionGateError::LockError)?
+            .insert(reservation.id.clone(), pending);
+        Ok(reservation)
+    }
+
+    /// Releases every reservation past its deadline, refunding its debit and
+    /// recording a failed trace. Holds left in the store by an earlier gate,
+    /// e.g. before a restart, are released the same way. Runs before each
+    /// admission; call it periodically to free budgets sooner. Compensations
+    /// run without the gate's reservation lock held, and one that fails is
+    /// only recorded in its trace, so it does not fail the caller. Returns
+    /// how many were released.
+    pub fn release_expired(&self) -> Result<usize, ExecutionGateError> {
+        let now = self.clock.now();
+        let (expired, recovered) = {
+            let mut reservations = self
+                .reservations
+                .lock()
+                .map_err(|_| ExecutionGateError::LockError)?;
+            let ids: Vec<String> = reservations
+                .iter()
+                .filter(|(_, pending)| pending.expires_at <= now)
+                .map(|(id, _)| id.clone())
+                .collect();
+            let expired: Vec<(String, PendingReservation)> = ids
+                .into_iter()
+                .filter_map(|id| reservations.remove(&id).map(|pending| (id, pending)))
+                .collect();
+            let recovered: Vec<ReservationHold> = self
+                .consumed
+                .holds()?
+                .into_iter()
+                .filter(|hold| {
+                    hold.expires_at <= now
+                        && !reservations.contains_key(&hold.reservation_id)
+                        && !expired.iter().any(|(id, _)| *id == hold.reservation_id)
+                })
+                .collect();
+            (expired, recovered)
+        };
+        if let Ok(mut closed) = self.closed.lock() {
+            closed.retain(|_, (_, forget_at)| *forget_at > now);
+        }
+
+        let mut released = 0;
+        let mut first_error = None;
+        for (id, pending) in expired {
+            if let Err(err) = self.consumed.release_hold(&id) {
+                // Still held, so the next sweep retries it.
+                if let Ok(mut reservations) = self.reservations.lock() {
+                    reservations.insert(id, pending);
+                }
+                first_error.get_or_insert(err.into());
+                continue;
+            }
+            let attempt = Attempt {
+                au: &pending.au,
+                action_name: &pending.action_name,
+                action_scope: &pending.action_scope,
+                compensation: pending
+                    .compensation
+                    .as_deref()
+                    .map(|compensation| compensation as Compensation),
+            };
+            self.close_reservation(&id, Closure::Expired);
+            let err = ExecutionGateError::ReservationExpired(id);
+            match self.roll_back(&attempt, pending.admission, err.kind(), err.to_string()) {
+                Ok(()) | Err(ExecutionGateError::CompensationFailed(_)) => released += 1,
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        for hold in recovered {
+            let err = ExecutionGateError::ReservationExpired(hold.reservation_id.clone());
+            match self.release_recovered(&hold, err.kind(), err.to_string()) {
+                Ok(()) => {
+                    self.close_reservation(&hold.reservation_id, Closure::Expired);
+                    released += 1;
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(released),
+        }
+    }
+
+    /// Settles a reservation with the output of its action, emitting the
+    /// trace and liability record exactly as a successful closure would.
+    pub fn commit_reservation<T: TraceSummary>(
+        &self,
+        id: &str,
+        output: T,
+    ) -> Result<(DecisionTrace, LiabilityRecord), ExecutionGateError> {
+        let pending = self.take_reservation(id)?;
+        let (_, dt, lr) = self.settle_reservation(pending, Ok::<T, Infallible>(output))?;
+        Ok((dt, lr))
+    }
+
+    /// Settles a reservation whose action failed: the debit is refunded and
+    /// a failed trace carries `reason`. A hold recovered after a restart is
+    /// released the same way.
+    pub fn abort_reservation(&self, id: &str, reason: &str) -> Result<(), ExecutionGateError> {
+        let pending = match self.take_reservation(id) {
+            Ok(pending) => pending,
+            Err(ExecutionGateError::ReservationRecovered(_)) => {
+                return match self.recovered_hold(id)? {
+                    Some(hold) => {
+                        self.release_recovered(&hold, "action_failed", reason.to_string())?;
+                        self.close_reservation(id, Closure::Settled);
+                        Ok(())
+                    }
+                    None => Err(self.missing_reservation(id)?),
+                };
+            }
+            Err(err) => return Err(err),
+        };
+        match self.settle_reservation(pending, Err::<String, _>(reason)) {
+            Err(ActionError::Gate(err)) => Err(err),
+            _ => Ok(()),
         }
+    }
+
+    fn settle_reservation<T: TraceSummary, E: Display>(
+        &self,
+        pending: PendingReservation,
+        outcome: Result<T, E>,
+    ) -> Result<(T, DecisionTrace, LiabilityRecord), ActionError<E>> {
+        let attempt = Attempt {
+            au: &pending.au,
+            action_name: &pending.action_name,
+            action_scope: &pending.action_scope,
+            compensation: pending
+                .compensation
+                .as_deref()
+                .map(|compensation| compensation as Compensation),
+        };
+        self.settle(&attempt, pending.admission, outcome)
+    }
 
-        let mut guard = self
-            .consumed_au_ids
+    /// Removes a reservation for settlement and ends its hold. One that has
+    /// expired is released instead. A hold this gate did not make, recovered
+    /// from the store, is reported as `ReservationRecovered`, and a
+    /// reservation that is no longer held as settled, expired or unknown.
+    fn take_reservation(&self, id: &str) -> Result<PendingReservation, ExecutionGateError> {
+        self.release_expired()?;
+        let mut reservations = self
+            .reservations
             .lock()
             .map_err(|_| ExecutionGateError::LockError)?;
+        let Some(pending) = reservations.remove(id) else {
+            return Err(self.missing_reservation(id)?);
+        };
+        // The hold ends before the settlement, so a crash in between leaves
+        // the debit standing rather than refunding it twice.
+        if let Err(err) = self.consumed.release_hold(id) {
+            reservations.insert(id.to_string(), pending);
+            return Err(err.into());
+        }
+        self.close_reservation(id, Closure::Settled);
+        Ok(pending)
+    }
+
+    /// Why `id` cannot be settled by this gate.
+    fn missing_reservation(&self, id: &str) -> Result<ExecutionGateError, ExecutionGateError> {
+        if self.recovered_hold(id)?.is_some() {
+            return Ok(ExecutionGateError::ReservationRecovered(id.to_string()));
+        }
+        let closed = self
+            .closed
+            .lock()
+            .map_err(|_| ExecutionGateError::LockError)?;
+        Ok(match closed.get(id) {
+            Some((Closure::Settled, _)) => ExecutionGateError::ReservationSettled(id.to_string()),
+            Some((Closure::Expired, _)) => ExecutionGateError::ReservationExpired(id.to_string()),
+            None => ExecutionGateError::UnknownReservation(id.to_string()),
+        })
+    }
+
+    /// Remembers how `id` was closed for one more reservation timeout.
+    fn close_reservation(&self, id: &str, closure: Closure) {
+        let forget_at = self.clock.now() + self.reservation_timeout;
+        if let Ok(mut closed) = self.closed.lock() {
+            closed.insert(id.to_string(), (closure, forget_at));
+        }
+    }
+
+    fn recovered_hold(&self, id: &str) -> Result<Option<ReservationHold>, ExecutionGateError> {
+        let holds = self.consumed.holds()?;
+        Ok(holds.into_iter().find(|hold| hold.reservation_id == id))
+    }
+
+    /// Ends a hold recovered from the store, credits its debit back and
+    /// records a failed trace with `error_kind`. A hold already ended by a
+    /// concurrent release is left alone.
+    fn release_recovered(
+        &self,
+        hold: &ReservationHold,
+        error_kind: &str,
+        message: String,
+    ) -> Result<(), ExecutionGateError> {
+        if !self.consumed.release_hold(&hold.reservation_id)? {
+            return Ok(());
+        }
+        self.consumed.credit(&hold.authority_id, &hold.amount)?;
+        let mut trace = DecisionTrace::rejected(
+            TraceOutcome::Failed,
+            hold.action_name.clone(),
+            hold.authority_id.clone(),
+            error_kind,
+            message,
+            self.clock.as_ref(),
+        );
+        self.record_as(
+            &hold.authority_hash,
+            &hold.action_name,
+            &hold.action_scope,
+            &mut trace,
+            None,
+        )
+    }
+
+    /// Async counterpart of `execute_with_authority` with the same reserve,
+    /// run, and commit-or-rollback semantics. The consumption store is only
+    /// touched before and after the action future, never across an `.await`.
+    #[cfg(feature = "async")]
+    pub async fn execute_with_authority_async<A, Fut>(
+        &self,
+        au: &AuthorityUnit,
+        action_fn: A,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<(DecisionTrace, LiabilityRecord), ExecutionGateError>
+    where
+        A: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<String, String>>,
+    {
+        let (_, dt, lr) = self
+            .execute_typed_async(au, action_fn, action_name, action_scope)
+            .await?;
+        Ok((dt, lr))
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn execute_typed_async<T, E, A, Fut>(
+        &self,
+        au: &AuthorityUnit,
+        action_fn: A,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<(T, DecisionTrace, LiabilityRecord), ActionError<E>>
+    where
+        T: TraceSummary,
+        E: Display,
+        A: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<T, E>>,
+    {
+        let attempt = Attempt {
+            au,
+            action_name,
+            action_scope,
+            compensation: None,
+        };
+        let admission = self.admit(&attempt)?;
+        let outcome = action_fn().await;
+        self.settle(&attempt, admission, outcome)
+    }
+
+    /// Async counterpart of `execute_with_compensation`. The compensation
+    /// runs synchronously once the action future has failed.
+    #[cfg(feature = "async")]
+    pub async fn execute_with_compensation_async<A, Fut>(
+        &self,
+        au: &AuthorityUnit,
+        action_fn: A,
+        compensation: Compensation<'_>,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<(DecisionTrace, LiabilityRecord), ExecutionGateError>
+    where
+        A: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<String, String>>,
+    {
+        let (_, dt, lr) = self
+            .execute_typed_with_compensation_async(
+                au,
+                action_fn,
+                compensation,
+                action_name,
+                action_scope,
+            )
+            .await?;
+        Ok((dt, lr))
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn execute_typed_with_compensation_async<T, E, A, Fut>(
+        &self,
+        au: &AuthorityUnit,
+        action_fn: A,
+        compensation: Compensation<'_>,
+        action_name: &str,
+        action_scope: &str,
+    ) -> Result<(T, DecisionTrace, LiabilityRecord), ActionError<E>>
+    where
+        T: TraceSummary,
+        E: Display,
+        A: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<T, E>>,
+    {
+        let attempt = Attempt {
+            au,
+            action_name,
+            action_scope,
+            compensation: Some(compensation),
+        };
+        let admission = self.admit(&attempt)?;
+        let outcome = action_fn().await;
+        self.settle(&attempt, admission, outcome)
+    }
 
-        if guard.contains(&au.id) {
-            return Err(ExecutionGateError::AlreadyConsumed(au.id.clone()));
+    /// Validates the attempt's unit for its scope, durably debits the unit's
+    /// budget and records an admitted trace. A refusal is recorded as a
+    /// denied trace before it is returned; if the admitted trace cannot be
+    /// written, the debit is credited back and the action must not run.
+    fn admit(&self, attempt: &Attempt) -> Result<Admission, ExecutionGateError> {
+        self.release_expired()?;
+        let mut policy = Vec::new();
+        match self.check_and_debit(attempt, &mut policy) {
+            Ok(admission) => {
+                // The action may only run once its debit is on record.
+                let mut trace = DecisionTrace {
+                    outcome: TraceOutcome::Admitted,
+                    ..DecisionTrace::with_clock(
+                        attempt.action_name.to_string(),
+                        attempt.au.id.clone(),
+                        String::new(),
+                        self.clock.as_ref(),
+                    )
+                };
+                trace.policy = admission.policy.clone();
+                if let Err(err) = self.record(attempt, &mut trace, None) {
+                    self.consumed.credit(&attempt.au.id, &attempt.au.price)?;
+                    self.release_execution(attempt, admission.admitted_at);
+                    return Err(err);
+                }
+                Ok(admission)
+            }
+            Err(err) => {
+                let mut trace = DecisionTrace::rejected(
+                    TraceOutcome::Denied,
+                    attempt.action_name.to_string(),
+                    attempt.au.id.clone(),
+                    err.kind(),
+                    err.to_string(),
+                    self.clock.as_ref(),
+                );
+                trace.policy = policy;
+                self.record(attempt, &mut trace, None)?;
+                Err(err)
+            }
+        }
+    }
+
+    fn check_and_debit(
+        &self,
+        attempt: &Attempt,
+        policy: &mut Vec<RuleEvaluation>,
+    ) -> Result<Admission, ExecutionGateError> {
+        let (au, action_scope) = (attempt.au, attempt.action_scope);
+        if !(self.validator)(au) {
+            return Err(ExecutionGateError::InvalidAuthority(au.id.clone()));
+        }
+
+        let budget = au.effective_budget();
+        if !budget.admits(&self.consumed.usage(&au.id)?, &au.price) {
+            return Err(Self::exhausted(au));
         }
 
         if !au.can_consume(action_scope) {
@@ -69,22 +949,259 @@ impl<F: Fn(&AuthorityUnit) -> bool + Send + Sync> ExecutionGate<F> {
             });
         }
 
-        guard.insert(au.id.clone());
-        drop(guard);
+        let line_items = self
+            .apportionment
+            .apportion(au.issuer(), &au.delegation_chain, &au.price)
+            .map_err(|err| ExecutionGateError::ApportionmentFailed(err.to_string()))?;
+
+        let now = self.clock.now();
+        if let Some(chain) = &self.policy {
+            let context = PolicyContext {
+                authority: au,
+                action_name: attempt.action_name,
+                action_scope,
+                now,
+            };
+            *policy = chain
+                .admit(&context)
+                .map_err(|_| ExecutionGateError::LockError)?;
+            if let Some(denial) = policy.iter().find(|evaluation| !evaluation.allowed) {
+                return Err(ExecutionGateError::PolicyDenied {
+                    rule: denial.rule.clone(),
+                    reason: denial.reason.clone(),
+                });
+            }
+        }
+
+        // Tokens are only taken once the policy chain has allowed the
+        // execution, and are given back if it is rolled back.
+        if let Some(limiter) = &self.rate_limiter {
+            let acquired = limiter
+                .acquire(&au.delegation_chain, action_scope, now)
+                .map_err(|err| match err {
+                    RateLimitError::Limited { key, retry_after } => {
+                        ExecutionGateError::RateLimited { key, retry_after }
+                    }
+                    _ => ExecutionGateError::LockError,
+                });
+            if let Err(err) = acquired {
+                self.release_policy(au, now);
+                return Err(err);
+            }
+        }
 
-        match action_fn() {
-            Ok(result) => {
-                let dt = DecisionTrace::new(action_name.to_string(), au.id.clone(), result);
-                let lr =
-                    LiabilityRecord::new(dt.id.clone(), au.id.clone(), au.price, au.scope.clone());
-                Ok((dt, lr))
+        // The store persists the debit before the action may run.
+        let usage = match self.consumed.debit(&au.id, &au.price, &budget) {
+            Ok(Some(usage)) => usage,
+            refused => {
+                self.release_execution(attempt, now);
+                refused?;
+                return Err(Self::exhausted(au));
+            }
+        };
+        Ok(Admission {
+            usage,
+            line_items,
+            policy: policy.clone(),
+            admitted_at: now,
+        })
+    }
+
+    /// Returns `amount` of the acting principal's spend to the policy chain,
+    /// alongside a refund to the store.
+    fn release_spend(&self, au: &AuthorityUnit, amount: &Price) {
+        if let Some(chain) = &self.policy {
+            let principal = au.delegation_chain.last().map(String::as_str);
+            let _ = chain.refund(principal.unwrap_or(""), amount);
+        }
+    }
+
+    /// Withdraws an execution admitted at `admitted_at` from the policy
+    /// chain's rate windows and spend and returns its rate limiter tokens,
+    /// alongside a credit to the store.
+    fn release_execution(&self, attempt: &Attempt, admitted_at: f64) {
+        self.release_policy(attempt.au, admitted_at);
+        if let Some(limiter) = &self.rate_limiter {
+            let chain = &attempt.au.delegation_chain;
+            let _ = limiter.release(chain, attempt.action_scope, self.clock.now());
+        }
+    }
+
+    fn release_policy(&self, au: &AuthorityUnit, admitted_at: f64) {
+        if let Some(chain) = &self.policy {
+            let principal = au.delegation_chain.last().map(String::as_str);
+            let _ = chain.release(principal.unwrap_or(""), admitted_at, &au.price);
+        }
+    }
+
+    /// Emits the trace and liability record for a completed action, or rolls
+    /// the execution back if the action failed. If the sink refuses the
+    /// executed trace, the error is returned but the debit stands; the
+    /// admitted trace already records the execution.
+    fn settle<T: TraceSummary, E: Display>(
+        &self,
+        attempt: &Attempt,
+        admission: Admission,
+        outcome: Result<T, E>,
+    ) -> Result<(T, DecisionTrace, LiabilityRecord), ActionError<E>> {
+        let au = attempt.au;
+        match outcome {
+            Ok(output) => {
+                let result = output.trace_summary();
+                let mut admission = admission;
+                let priced = self.charge(attempt, &mut admission, &result, output.priced_output());
+                let charged = match priced {
+                    Ok(charged) => charged,
+                    Err(err) => {
+                        self.roll_back(attempt, admission, err.kind(), err.to_string())?;
+                        return Err(err.into());
+                    }
+                };
+                let mut dt = DecisionTrace::with_clock(
+                    attempt.action_name.to_string(),
+                    au.id.clone(),
+                    result,
+                    self.clock.as_ref(),
+                );
+                dt.policy = admission.policy;
+                let mut lr = LiabilityRecord::with_clock(
+                    dt.id.clone(),
+                    au.id.clone(),
+                    charged.clone(),
+                    au.scope.clone(),
+                    self.clock.as_ref(),
+                );
+                lr.quoted_price = self.pricer.as_ref().map(|_| au.price.clone());
+                lr.remaining_budget = au
+                    .budget
+                    .as_ref()
+                    .map(|budget| budget.remaining(&admission.usage));
+                lr.line_items = admission.line_items;
+                // The action has taken effect, so the unit stays consumed
+                // even if its trace cannot be written.
+                self.record(attempt, &mut dt, Some(&mut lr))?;
+                Ok((output, dt, lr))
             }
             Err(e) => {
-                if let Ok(mut guard) = self.consumed_au_ids.lock() {
-                    guard.remove(&au.id);
-                }
-                Err(ExecutionGateError::ActionFailed(e))
+                self.roll_back(attempt, admission, "action_failed", e.to_string())?;
+                Err(ActionError::Action(e))
+            }
+        }
+    }
+
+    /// Rolls back an admitted attempt whose action ran but did not complete.
+    /// Its compensation, if any, runs first. If that fails the action's
+    /// effects may remain, so the debit stands and `CompensationFailed` is
+    /// returned even if its trace cannot be written. Otherwise the debit is
+    /// refunded with a failed trace, then a compensated trace is recorded; a
+    /// sink refusing the latter does not hide the original failure.
+    fn roll_back(
+        &self,
+        attempt: &Attempt,
+        admission: Admission,
+        error_kind: &str,
+        message: String,
+    ) -> Result<(), ExecutionGateError> {
+        let summary = match attempt
+            .compensation
+            .map(|compensation| compensation(&message))
+        {
+            Some(Ok(summary)) => Some(summary),
+            Some(Err(reason)) => {
+                let err = ExecutionGateError::CompensationFailed(reason);
+                let mut trace = DecisionTrace::rejected(
+                    TraceOutcome::Failed,
+                    attempt.action_name.to_string(),
+                    attempt.au.id.clone(),
+                    err.kind(),
+                    err.to_string(),
+                    self.clock.as_ref(),
+                );
+                trace.policy = admission.policy;
+                let _ = self.record(attempt, &mut trace, None);
+                return Err(err);
             }
+            None => None,
+        };
+        self.fail(attempt, admission, error_kind, message)?;
+        if let Some(summary) = summary {
+            let mut trace = DecisionTrace {
+                outcome: TraceOutcome::Compensated,
+                ..DecisionTrace::with_clock(
+                    attempt.action_name.to_string(),
+                    attempt.au.id.clone(),
+                    summary,
+                    self.clock.as_ref(),
+                )
+            };
+            let _ = self.record(attempt, &mut trace, None);
+        }
+        Ok(())
+    }
+
+    /// Refunds an admitted attempt that did not complete and records a
+    /// failed trace with `error_kind`.
+    fn fail(
+        &self,
+        attempt: &Attempt,
+        admission: Admission,
+        error_kind: &str,
+        message: String,
+    ) -> Result<(), ExecutionGateError> {
+        let au = attempt.au;
+        self.consumed.credit(&au.id, &au.price)?;
+        self.release_execution(attempt, admission.admitted_at);
+        let mut trace = DecisionTrace::rejected(
+            TraceOutcome::Failed,
+            attempt.action_name.to_string(),
+            au.id.clone(),
+            error_kind,
+            message,
+            self.clock.as_ref(),
+        );
+        trace.policy = admission.policy;
+        self.record(attempt, &mut trace, None)
+    }
+
+    /// Asks the pricer, if any, what the completed execution costs. The
+    /// unspent part of the reserved price is refunded and the admission's
+    /// usage and line items are revised to the charged amount. On error the
+    /// admission is left as it was.
+    fn charge(
+        &self,
+        attempt: &Attempt,
+        admission: &mut Admission,
+        result: &str,
+        output: Option<&dyn Any>,
+    ) -> Result<Price, ExecutionGateError> {
+        let au = attempt.au;
+        let Some(pricer) = &self.pricer else {
+            return Ok(au.price.clone());
+        };
+        let context = PricingContext {
+            authority: au,
+            action_name: attempt.action_name,
+            action_scope: attempt.action_scope,
+            result,
+            output,
+        };
+        let charged = bounded_charge(&au.price, pricer.price(&context))
+            .map_err(|err| ExecutionGateError::PricingFailed(err.to_string()))?;
+        let refund = au.price.with_amount(au.price.amount - charged.amount);
+        if refund.amount > 0 {
+            let line_items = self
+                .apportionment
+                .apportion(au.issuer(), &au.delegation_chain, &charged)
+                .map_err(|err| ExecutionGateError::ApportionmentFailed(err.to_string()))?;
+            let usage = admission
+                .usage
+                .refunded(&refund)
+                .ok_or_else(|| StoreError::InvalidAmount(au.id.clone()))?;
+            self.consumed.refund(&au.id, &refund)?;
+            self.release_spend(au, &refund);
+            admission.usage = usage;
+            admission.line_items = line_items;
         }
+        Ok(charged)
     }
 }
@@ -0,0 +1,62 @@
+use crate::core::authority::AuthorityUnit;
+use crate::core::price::Price;
+use crate::core::scope::Scope;
+use std::cmp::Ordering;
+
+/// A declarative constraint on which authority units may be issued.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum IssuanceRule {
+    /// Units issued by `issuer`, or delegated through it, must fall within
+    /// one of `scopes`. Units that never pass through it are unaffected.
+    ScopeAllowList { issuer: String, scopes: Vec<String> },
+    /// No unit may be priced above this, or in another unit.
+    MaxPrice(Price),
+    /// No unit may be delegated through more than this many hops.
+    MaxDelegationDepth(usize),
+}
+
+impl IssuanceRule {
+    /// Checks `au` against the rule, returning why it is refused.
+    pub fn evaluate(&self, au: &AuthorityUnit) -> Result<(), String> {
+        match self {
+            IssuanceRule::ScopeAllowList { issuer, scopes } => {
+                if au.issuer() != issuer && !au.delegation_chain.contains(issuer) {
+                    return Ok(());
+                }
+                let allowed = Scope::parse(&au.scope).is_ok_and(|scope| {
+                    scopes.iter().any(|pattern| {
+                        Scope::parse(pattern).is_ok_and(|pattern| scope.is_subset_of(&pattern))
+                    })
+                });
+                if allowed {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "principal '{}' may not grant scope '{}'",
+                        issuer, au.scope
+                    ))
+                }
+            }
+            IssuanceRule::MaxPrice(max) => match au.price.checked_cmp(max) {
+                Ok(Ordering::Greater) => {
+                    Err(format!("price {} exceeds the maximum {}", au.price, max))
+                }
+                Ok(_) => Ok(()),
+                Err(err) => Err(err.to_string()),
+            },
+            IssuanceRule::MaxDelegationDepth(max) => {
+                let depth = au.delegation_chain.len().saturating_sub(1);
+                if depth > *max {
+                    Err(format!(
+                        "delegation depth {} exceeds the maximum {}",
+                        depth, max
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use crate::core::canonical::{Canonical, CanonicalValue};
+use crate::core::clock::{Clock, SystemClock};
+use crate::core::price::{Price, PriceError};
+use crate::core::trace::LiabilityRecord;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum LedgerError {
+    #[error("liability record {0} is already posted")]
+    DuplicateRecord(String),
+    #[error("liability record {record_id} apportions {apportioned} of price {price}")]
+    Unbalanced {
+        record_id: String,
+        price: Price,
+        apportioned: Price,
+    },
+    #[error("ledger arithmetic failed: {0}")]
+    Price(#[from] PriceError),
+    #[error("ledger invariant violated: {0}")]
+    InvariantViolation(String),
+    #[error("statements end at {actual}, expected anchored head {expected}")]
+    HeadMismatch { expected: String, actual: String },
+    #[error("internal lock error")]
+    LockError,
+}
+
+/// A ledger account: a principal liable for executions, or the scope the
+/// executions were performed under.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Account {
+    Party(String),
+    Scope(String),
+}
+
+impl Canonical for Account {
+    fn canonical_value(&self) -> CanonicalValue {
+        match self {
+            Account::Party(party) => {
+                CanonicalValue::object([("party", CanonicalValue::str(party))])
+            }
+            Account::Scope(scope) => {
+                CanonicalValue::object([("scope", CanonicalValue::str(scope))])
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum EntrySide {
+    Debit,
+    Credit,
+}
+
+impl EntrySide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntrySide::Debit => "debit",
+            EntrySide::Credit => "credit",
+        }
+    }
+}
+
+/// One side of a posted liability record. Each record debits every liable
+/// party by its line item and credits the record's scope by the full price.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Posting {
+    pub record_id: String,
+    pub account: Account,
+    pub side: EntrySide,
+    pub amount: Price,
+}
+
+impl Canonical for Posting {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("record_id", CanonicalValue::str(&self.record_id)),
+            ("account", self.account.canonical_value()),
+            ("side", CanonicalValue::str(self.side.as_str())),
+            ("amount", self.amount.canonical_value()),
+        ])
+    }
+}
+
+/// The closed books of one settlement period. Statements are chained by
+/// hash; `LiabilityLedger::verify_statements_head` checks them against a
+/// head hash recorded elsewhere, which detects any altered or dropped
+/// statement.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Statement {
+    pub period: u64,
+    /// The unit every amount in the statement is priced in.
+    pub unit: String,
+    pub opened_at: f64,
+    pub closed_at: f64,
+    pub postings: Vec<Posting>,
+    /// Amount each principal owes for the period.
+    pub party_balances: BTreeMap<String, Price>,
+    /// Amount charged under each scope for the period.
+    pub scope_balances: BTreeMap<String, Price>,
+    pub prev_hash: Option<String>,
+}
+
+impl Statement {
+    /// SHA-256 of the statement's canonical JSON.
+    pub fn hash(&self) -> String {
+        self.canonical_value().sha256_hex()
+    }
+
+    /// Total billed for the period.
+    pub fn total(&self) -> Result<Price, PriceError> {
+        Price::checked_sum(&self.unit, self.party_balances.values())
+    }
+}
+
+fn balances_value(balances: &BTreeMap<String, Price>) -> CanonicalValue {
+    CanonicalValue::Object(
+        balances
+            .iter()
+            .map(|(key, amount)| (key.clone(), amount.canonical_value()))
+            .collect(),
+    )
+}
+
+impl Canonical for Statement {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("period", CanonicalValue::UInt(self.period)),
+            ("unit", CanonicalValue::str(&self.unit)),
+            ("opened_at", CanonicalValue::Float(self.opened_at)),
+            ("closed_at", CanonicalValue::Float(self.closed_at)),
+            (
+                "postings",
+                CanonicalValue::Array(
+                    self.postings
+                        .iter()
+                        .map(Canonical::canonical_value)
+                        .collect(),
+                ),
+            ),
+            ("party_balances", balances_value(&self.party_balances)),
+            ("scope_balances", balances_value(&self.scope_balances)),
+            (
+                "prev_hash",
+                CanonicalValue::opt_str(self.prev_hash.as_deref()),
+            ),
+        ])
+    }
+}
+
+#[derive(Debug, Default)]
+struct LedgerState {
+    opened_at: f64,
+    postings: Vec<Posting>,
+    party_balances: BTreeMap<String, Price>,
+    scope_balances: BTreeMap<String, Price>,
+    posted: HashSet<String>,
+    statements: Vec<Statement>,
+}
+
+/// Accumulates liability records into per-principal and per-scope balances
+/// for the open settlement period. Closing the period freezes those balances
+/// into a `Statement` and starts the next period from zero. A ledger keeps
+/// its books in a single unit and refuses records priced in any other.
+pub struct LiabilityLedger {
+    unit: String,
+    state: Arc<RwLock<LedgerState>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl LiabilityLedger {
+    pub fn new(unit: &str) -> Self {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let state = LedgerState {
+            opened_at: clock.now(),
+            ..LedgerState::default()
+        };
+        LiabilityLedger {
+            unit: unit.to_string(),
+            state: Arc::new(RwLock::new(state)),
+            clock,
+        }
+    }
+
+    /// Reads period boundaries from `clock`; the first period opens at its
+    /// current time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        if let Ok(mut state) = self.state.write() {
+            state.opened_at = clock.now();
+        }
+        self.clock = clock;
+        self
+    }
+
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    /// Posts a record into the open period. A record is posted at most once,
+    /// across all periods, and its line items must account for its price.
+    pub fn post(&self, record: &LiabilityRecord) -> Result<(), LedgerError> {
+        let mut state = self.state.write().map_err(|_| LedgerError::LockError)?;
+        if state.posted.contains(&record.id) {
+            return Err(LedgerError::DuplicateRecord(record.id.clone()));
+        }
+        record.price.ensure_same_unit(&Price::zero(&self.unit))?;
+        let apportioned = Price::checked_sum(
+            &self.unit,
+            record.line_items.iter().map(|line| &line.amount),
+        )?;
+        if apportioned != record.price {
+            return Err(LedgerError::Unbalanced {
+                record_id: record.id.clone(),
+                price: record.price.clone(),
+                apportioned,
+            });
+        }
+
+        let mut postings: Vec<Posting> = record
+            .line_items
+            .iter()
+            .map(|line| Posting {
+                record_id: record.id.clone(),
+                account: Account::Party(line.party.clone()),
+                side: EntrySide::Debit,
+                amount: line.amount.clone(),
+            })
+            .collect();
+        postings.push(Posting {
+            record_id: record.id.clone(),
+            account: Account::Scope(record.scope.clone()),
+            side: EntrySide::Credit,
+            amount: record.price.clone(),
+        });
+
+        // Apply to copies so an overflow leaves the ledger untouched.
+        let mut party_balances = state.party_balances.clone();
+        let mut scope_balances = state.scope_balances.clone();
+        for posting in &postings {
+            let (balances, key) = match &posting.account {
+                Account::Party(party) => (&mut party_balances, party),
+                Account::Scope(scope) => (&mut scope_balances, scope),
+            };
+            let balance = balances
+                .entry(key.clone())
+                .or_insert_with(|| Price::zero(&self.unit));
+            *balance = balance.checked_add(&posting.amount)?;
+        }
+
+        state.party_balances = party_balances;
+        state.scope_balances = scope_balances;
+        state.postings.extend(postings);
+        state.posted.insert(record.id.clone());
+        Ok(())
+    }
+
+    /// Amount `party` owes in the open period.
+    pub fn party_balance(&self, party: &str) -> Result<Price, LedgerError> {
+        let state = self.state.read().map_err(|_| LedgerError::LockError)?;
+        Ok(state
+            .party_balances
+            .get(party)
+            .cloned()
+            .unwrap_or_else(|| Price::zero(&self.unit)))
+    }
+
+    /// Amount charged under `scope` in the open period.
+    pub fn scope_balance(&self, scope: &str) -> Result<Price, LedgerError> {
+        let state = self.state.read().map_err(|_| LedgerError::LockError)?;
+        Ok(state
+            .scope_balances
+            .get(scope)
+            .cloned()
+            .unwrap_or_else(|| Price::zero(&self.unit)))
+    }
+
+    pub fn party_balances(&self) -> Result<BTreeMap<String, Price>, LedgerError> {
+        let state = self.state.read().map_err(|_| LedgerError::LockError)?;
+        Ok(state.party_balances.clone())
+    }
+
+    pub fn scope_balances(&self) -> Result<BTreeMap<String, Price>, LedgerError> {
+        let state = self.state.read().map_err(|_| LedgerError::LockError)?;
+        Ok(state.scope_balances.clone())
+    }
+
+    /// Closes the open period, returning its statement, and opens the next.
+    pub fn close_period(&self) -> Result<Statement, LedgerError> {
+        let mut state = self.state.write().map_err(|_| LedgerError::LockError)?;
+        let closed_at = self.clock.now();
+        let statement = Statement {
+            period: state.statements.len() as u64,
+            unit: self.unit.clone(),
+            opened_at: state.opened_at,
+            closed_at,
+            postings: std::mem::take(&mut state.postings),
+            party_balances: std::mem::take(&mut state.party_balances),
+            scope_balances: std::mem::take(&mut state.scope_balances),
+            prev_hash: state.statements.last().map(Statement::hash),
+        };
+        state.opened_at = closed_at;
+        state.statements.push(statement.clone());
+        Ok(statement)
+    }
+
+    /// Every statement closed so far, oldest first.
+    pub fn statements(&self) -> Result<Vec<Statement>, LedgerError> {
+        let state = self.state.read().map_err(|_| LedgerError::LockError)?;
+        Ok(state.statements.clone())
+    }
+
+    /// Hash of the last closed statement, for anchoring the chain.
+    pub fn statement_head(&self) -> Result<Option<String>, LedgerError> {
+        let state = self.state.read().map_err(|_| LedgerError::LockError)?;
+        Ok(state.statements.last().map(Statement::hash))
+    }
+
+    /// Checks the double-entry invariants of every closed statement and of
+    /// the open period: each record's debits equal its credits, and balances
+    /// equal the sum of their postings.
+    pub fn check_invariants(&self) -> Result<(), LedgerError> {
+        let state = self.state.read().map_err(|_| LedgerError::LockError)?;
+        Self::verify_statements(&state.statements)?;
+        check_books(
+            "open period",
+            &self.unit,
+            &state.postings,
+            &state.party_balances,
+            &state.scope_balances,
+        )
+    }
+
+    /// Checks a sequence of stored statements, oldest first: each must
+    /// balance and link to its predecessor. Not sufficient on its own, since
+    /// a change to the last statement or a dropped tail goes unseen; see
+    /// `verify_statements_head`.
+    pub fn verify_statements(statements: &[Statement]) -> Result<(), LedgerError> {
+        let mut prev_hash: Option<String> = None;
+        for (index, statement) in statements.iter().enumerate() {
+            let label = format!("statement {}", index);
+            if statement.period != index as u64 {
+                return Err(LedgerError::InvariantViolation(format!(
+                    "{} has period {}",
+                    label, statement.period
+                )));
+            }
+            if statement.prev_hash != prev_hash {
+                return Err(LedgerError::InvariantViolation(format!(
+                    "{} does not link to its predecessor",
+                    label
+                )));
+            }
+            check_books(
+                &label,
+                &statement.unit,
+                &statement.postings,
+                &statement.party_balances,
+                &statement.scope_balances,
+            )?;
+            prev_hash = Some(statement.hash());
+        }
+        Ok(())
+    }
+
+    /// Checks the statements and that they end at the anchored `expected`
+    /// head, which pins every statement's content.
+    pub fn verify_statements_head(
+        statements: &[Statement],
+        expected: &str,
+    ) -> Result<(), LedgerError> {
+        Self::verify_statements(statements)?;
+        let actual = statements.last().map(Statement::hash).unwrap_or_default();
+        if actual != expected {
+            return Err(LedgerError::HeadMismatch {
+                expected: expected.to_string(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn check_books(
+    label: &str,
+    unit: &str,
+    postings: &[Posting],
+    party_balances: &BTreeMap<String, Price>,
+    scope_balances: &BTreeMap<String, Price>,
+) -> Result<(), LedgerError> {
+    let violation =
+        |reason: String| LedgerError::InvariantViolation(format!("{}: {}", label, reason));
+    let foreign = postings
+        .iter()
+        .map(|posting| &posting.amount)
+        .chain(party_balances.values())
+        .chain(scope_balances.values())
+        .find(|amount| amount.unit != unit);
+    if let Some(amount) = foreign {
+        return Err(violation(format!(
+            "amount in '{}' in books kept in '{}'",
+            amount.unit, unit
+        )));
+    }
+
+    let mut per_record: BTreeMap<&str, i128> = BTreeMap::new();
+    let mut parties: BTreeMap<String, i128> = BTreeMap::new();
+    let mut scopes: BTreeMap<String, i128> = BTreeMap::new();
+    for posting in postings {
+        let signed = match posting.side {
+            EntrySide::Debit => posting.amount.amount as i128,
+            EntrySide::Credit => -(posting.amount.amount as i128),
+        };
+        *per_record.entry(&posting.record_id).or_insert(0) += signed;
+        let (totals, key) = match &posting.account {
+            Account::Party(party) => (&mut parties, party),
+            Account::Scope(scope) => (&mut scopes, scope),
+        };
+        *totals.entry(key.clone()).or_insert(0) += posting.amount.amount as i128;
+    }
+
+    if let Some((record_id, _)) = per_record.iter().find(|(_, net)| **net != 0) {
+        return Err(violation(format!(
+            "debits and credits of record {} differ",
+            record_id
+        )));
+    }
+    let widen = |balances: &BTreeMap<String, Price>| -> BTreeMap<String, i128> {
+        balances
+            .iter()
+            .filter(|(_, balance)| balance.amount != 0)
+            .map(|(key, balance)| (key.clone(), balance.amount as i128))
+            .collect()
+    };
+    parties.retain(|_, amount| *amount != 0);
+    scopes.retain(|_, amount| *amount != 0);
+    if widen(party_balances) != parties {
+        return Err(violation(
+            "party balances do not match postings".to_string(),
+        ));
+    }
+    if widen(scope_balances) != scopes {
+        return Err(violation(
+            "scope balances do not match postings".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -1,4 +1,11 @@
-use crate::core::authority::{current_timestamp, AuthorityUnit};
+use crate::core::authority::AuthorityUnit;
+use crate::core::clock::{Clock, SystemClock};
+use crate::core::issuance::IssuanceRule;
+use crate::core::price::Price;
+use crate::core::revocation::RevocationList;
+use crate::core::signing::KeyRegistry;
+use crate::core::trace::{DecisionTrace, TraceLog, TraceOutcome};
+use ed25519_dalek::VerifyingKey;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use thiserror::Error;
@@ -9,12 +16,29 @@ pub enum ManagerError {
     DuplicateAuthority(String),
     #[error("invalid authority: {0}")]
     InvalidAuthority(String),
+    #[error("invalid authority signature: {0}")]
+    InvalidSignature(String),
+    #[error("invalid delegation: {0}")]
+    InvalidDelegation(String),
+    #[error("authority {0} is already revoked")]
+    AlreadyRevoked(String),
+    #[error("issuance policy '{rule}' violated: {reason}")]
+    PolicyViolation { rule: String, reason: String },
+    #[error("issuance rule {0} already exists")]
+    DuplicateRule(String),
     #[error("internal lock error")]
     LockError,
 }
 
 pub struct AuthorityManager {
     authorities: Arc<RwLock<HashMap<String, AuthorityUnit>>>,
+    trusted_issuers: Arc<RwLock<KeyRegistry>>,
+    delegator_keys: Arc<RwLock<KeyRegistry>>,
+    root_authorities: Arc<RwLock<HashMap<String, (String, Price)>>>,
+    revocations: Arc<RwLock<RevocationList>>,
+    revocation_log: Arc<RwLock<TraceLog>>,
+    issuance_rules: Arc<RwLock<Vec<(String, IssuanceRule)>>>,
+    clock: Arc<dyn Clock>,
     max_age_seconds: i64,
 }
 
@@ -26,22 +50,158 @@ impl Default for AuthorityManager {
 
 impl AuthorityManager {
     pub fn new() -> Self {
-        AuthorityManager {
-            authorities: Arc::new(RwLock::new(HashMap::new())),
-            max_age_seconds: 3600,
-        }
+        Self::with_max_age(3600)
     }
 
     pub fn with_max_age(max_age_seconds: i64) -> Self {
         AuthorityManager {
             authorities: Arc::new(RwLock::new(HashMap::new())),
+            trusted_issuers: Arc::new(RwLock::new(KeyRegistry::new())),
+            delegator_keys: Arc::new(RwLock::new(KeyRegistry::new())),
+            root_authorities: Arc::new(RwLock::new(HashMap::new())),
+            revocations: Arc::new(RwLock::new(RevocationList::new())),
+            revocation_log: Arc::new(RwLock::new(TraceLog::new())),
+            issuance_rules: Arc::new(RwLock::new(Vec::new())),
+            clock: Arc::new(SystemClock),
             max_age_seconds,
         }
     }
 
+    /// Reads validation and revocation times from `clock` instead of the
+    /// system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Registers an issuer whose signed authority units are accepted even when
+    /// they were not issued through this manager.
+    pub fn trust_issuer(&self, issuer: &str, key: VerifyingKey) -> Result<(), ManagerError> {
+        self.trusted_issuers
+            .write()
+            .map_err(|_| ManagerError::LockError)?
+            .insert(issuer, key);
+        Ok(())
+    }
+
+    pub fn distrust_issuer(&self, issuer: &str) -> Result<(), ManagerError> {
+        self.trusted_issuers
+            .write()
+            .map_err(|_| ManagerError::LockError)?
+            .remove(issuer);
+        Ok(())
+    }
+
+    /// Registers the key a principal uses to sign delegation records.
+    pub fn register_delegator(
+        &self,
+        principal: &str,
+        key: VerifyingKey,
+    ) -> Result<(), ManagerError> {
+        self.delegator_keys
+            .write()
+            .map_err(|_| ManagerError::LockError)?
+            .insert(principal, key);
+        Ok(())
+    }
+
+    /// Records the scope and price `issuer` holds at the root of every chain
+    /// it delegates. Delegated units are only accepted from issuers with a
+    /// root authority, and a unit's first hop may not exceed it.
+    pub fn grant_root_authority(
+        &self,
+        issuer: &str,
+        scope: &str,
+        price: impl Into<Price>,
+    ) -> Result<(), ManagerError> {
+        self.root_authorities
+            .write()
+            .map_err(|_| ManagerError::LockError)?
+            .insert(issuer.to_string(), (scope.to_string(), price.into()));
+        Ok(())
+    }
+
+    /// Adds a rule every issued unit must satisfy. A violation is reported
+    /// under `name`, which must be unique.
+    pub fn add_issuance_rule(&self, name: &str, rule: IssuanceRule) -> Result<(), ManagerError> {
+        let mut rules = self
+            .issuance_rules
+            .write()
+            .map_err(|_| ManagerError::LockError)?;
+        if rules.iter().any(|(existing, _)| existing == name) {
+            return Err(ManagerError::DuplicateRule(name.to_string()));
+        }
+        rules.push((name.to_string(), rule));
+        Ok(())
+    }
+
+    pub fn remove_issuance_rule(&self, name: &str) -> Result<bool, ManagerError> {
+        let mut rules = self
+            .issuance_rules
+            .write()
+            .map_err(|_| ManagerError::LockError)?;
+        let before = rules.len();
+        rules.retain(|(rule_name, _)| rule_name != name);
+        Ok(rules.len() != before)
+    }
+
+    fn check_issuance_rules(&self, au: &AuthorityUnit) -> Result<(), ManagerError> {
+        let rules = self
+            .issuance_rules
+            .read()
+            .map_err(|_| ManagerError::LockError)?;
+        for (name, rule) in rules.iter() {
+            rule.evaluate(au)
+                .map_err(|reason| ManagerError::PolicyViolation {
+                    rule: name.clone(),
+                    reason,
+                })?;
+        }
+        Ok(())
+    }
+
+    fn verify_delegations(&self, au: &AuthorityUnit) -> Result<(), ManagerError> {
+        let roots = self
+            .root_authorities
+            .read()
+            .map_err(|_| ManagerError::LockError)?;
+        match roots.get(au.issuer()) {
+            Some((scope, price)) => au
+                .validate_root(scope, price)
+                .map_err(|err| ManagerError::InvalidDelegation(err.to_string()))?,
+            None if !au.delegations.is_empty() => {
+                return Err(ManagerError::InvalidDelegation(format!(
+                    "issuer '{}' holds no root authority",
+                    au.issuer()
+                )))
+            }
+            None => {}
+        }
+        drop(roots);
+        if au.delegations.is_empty() {
+            return Ok(());
+        }
+        let delegators = self
+            .delegator_keys
+            .read()
+            .map_err(|_| ManagerError::LockError)?;
+        au.verify_delegations(&delegators)
+            .map_err(|err| ManagerError::InvalidDelegation(err.to_string()))
+    }
+
     pub fn issue_authority(&self, au: AuthorityUnit) -> Result<(), ManagerError> {
         au.validate_invariants()
             .map_err(|err| ManagerError::InvalidAuthority(err.to_string()))?;
+        if au.signature.is_some() {
+            let trusted = self
+                .trusted_issuers
+                .read()
+                .map_err(|_| ManagerError::LockError)?;
+            au.verify_signature(&trusted)
+                .map_err(|err| ManagerError::InvalidSignature(err.to_string()))?;
+        }
+        self.verify_delegations(&au)?;
+        self.check_issuance_rules(&au)?;
         let mut guard = self
             .authorities
             .write()
@@ -53,17 +213,112 @@ impl AuthorityManager {
         Ok(())
     }
 
+    /// Revokes a single unit, whether or not it was issued here. Takes effect
+    /// on the next validation and returns the trace recording the revocation.
+    pub fn revoke_authority(
+        &self,
+        au_id: &str,
+        reason: &str,
+    ) -> Result<DecisionTrace, ManagerError> {
+        self.record_revocation("revoke_authority", au_id, reason, |list| {
+            list.revoke_authority(au_id, reason)
+        })
+    }
+
+    /// Revokes a principal, invalidating every unit whose delegation chain
+    /// includes it. The returned trace's `authority_id` names the principal.
+    pub fn revoke_principal(
+        &self,
+        principal: &str,
+        reason: &str,
+    ) -> Result<DecisionTrace, ManagerError> {
+        self.record_revocation("revoke_principal", principal, reason, |list| {
+            list.revoke_principal(principal, reason)
+        })
+    }
+
+    fn record_revocation(
+        &self,
+        action_name: &str,
+        target: &str,
+        reason: &str,
+        revoke: impl FnOnce(&mut RevocationList) -> bool,
+    ) -> Result<DecisionTrace, ManagerError> {
+        let mut revocations = self
+            .revocations
+            .write()
+            .map_err(|_| ManagerError::LockError)?;
+        let mut log = self
+            .revocation_log
+            .write()
+            .map_err(|_| ManagerError::LockError)?;
+        if !revoke(&mut revocations) {
+            return Err(ManagerError::AlreadyRevoked(target.to_string()));
+        }
+        let trace = DecisionTrace {
+            outcome: TraceOutcome::Revoked,
+            ..DecisionTrace::with_clock(
+                action_name.to_string(),
+                target.to_string(),
+                reason.to_string(),
+                self.clock.as_ref(),
+            )
+        };
+        Ok(log.append(trace).clone())
+    }
+
+    pub fn is_revoked(&self, au: &AuthorityUnit) -> bool {
+        self.revocations
+            .read()
+            .map(|revocations| revocations.revocation_reason(au).is_some())
+            .unwrap_or(true)
+    }
+
+    /// Hash-chained log of every revocation trace emitted by this manager.
+    pub fn revocation_log(&self) -> Result<TraceLog, ManagerError> {
+        self.revocation_log
+            .read()
+            .map(|log| log.clone())
+            .map_err(|_| ManagerError::LockError)
+    }
+
+    /// Accepts a unit that was issued through this manager, or one carrying a
+    /// valid signature from a trusted issuer. A signed unit is always checked
+    /// against the issuer registry, wherever it was issued, and one issued
+    /// elsewhere must also satisfy this manager's issuance rules.
     pub fn validate_authority(&self, au: &AuthorityUnit) -> bool {
+        match self.revocations.read() {
+            Ok(revocations) if revocations.revocation_reason(au).is_none() => {}
+            _ => return false,
+        }
         let guard = match self.authorities.read() {
             Ok(g) => g,
             Err(_) => return false,
         };
-        match guard.get(&au.id) {
-            Some(stored_au) if stored_au == au && stored_au.validate_invariants().is_ok() => {
-                stored_au.is_valid(current_timestamp(), self.max_age_seconds)
+        let (known, issued_here) = match guard.get(&au.id) {
+            Some(stored_au) => (stored_au == au, true),
+            None => (au.signature.is_some(), false),
+        };
+        drop(guard);
+        if !known || au.validate_invariants().is_err() {
+            return false;
+        }
+        if au.signature.is_some() {
+            let trusted = match self.trusted_issuers.read() {
+                Ok(g) => g,
+                Err(_) => return false,
+            };
+            if au.verify_signature(&trusted).is_err() {
+                return false;
             }
-            _ => false,
         }
+        if self.verify_delegations(au).is_err() {
+            return false;
+        }
+        if !issued_here && self.check_issuance_rules(au).is_err() {
+            return false;
+        }
+        au.is_valid(self.clock.now(), self.max_age_seconds)
     }
 
     pub fn get_authority(&self, au_id: &str) -> Option<AuthorityUnit> {
@@ -1,4 +1,21 @@
+pub mod apportionment;
 pub mod authority;
+pub mod budget;
+pub mod canonical;
+pub mod clock;
+pub mod delegation;
 pub mod gate;
+pub mod issuance;
+pub mod ledger;
 pub mod manager;
+pub mod policy;
+pub mod price;
+pub mod pricing;
+pub mod ratelimit;
+pub mod replay;
+pub mod revocation;
+pub mod scope;
+pub mod signing;
+pub mod sink;
+pub mod store;
 pub mod trace;
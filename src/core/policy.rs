@@ -0,0 +1,343 @@
+use crate::core::authority::AuthorityUnit;
+use crate::core::canonical::{Canonical, CanonicalValue};
+use crate::core::price::Price;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, RwLock};
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum PolicyError {
+    #[error("policy rule {0} already exists")]
+    DuplicateRule(String),
+    #[error("invalid policy rule: {0}")]
+    InvalidRule(String),
+    #[error("internal lock error")]
+    LockError,
+}
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// A declarative guard evaluated by the gate before an action runs. Rate
+/// and spend limits apply to the acting principal, the last entry of the
+/// unit's delegation chain.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ExecutionRule {
+    /// Allows executions between two times of day, in seconds after UTC
+    /// midnight. A window whose start is after its end wraps past midnight.
+    TimeOfDay { start: u32, end: u32 },
+    /// At most `max_executions` per principal in any `window_seconds`. Only
+    /// executions that were not rolled back count.
+    RateLimit {
+        max_executions: u64,
+        window_seconds: f64,
+    },
+    /// Only the named actions may run.
+    ActionAllowList(Vec<String>),
+    /// A principal's cumulative charges may not exceed this.
+    MaxCumulativeSpend(Price),
+}
+
+impl ExecutionRule {
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        match self {
+            ExecutionRule::TimeOfDay { start, end } => {
+                if *start as f64 >= SECONDS_PER_DAY || *end as f64 > SECONDS_PER_DAY {
+                    return Err(PolicyError::InvalidRule(format!(
+                        "window {}..{} must lie within a day",
+                        start, end
+                    )));
+                }
+                if start == end {
+                    return Err(PolicyError::InvalidRule(format!(
+                        "window {}..{} is empty",
+                        start, end
+                    )));
+                }
+            }
+            ExecutionRule::RateLimit {
+                max_executions,
+                window_seconds,
+            } => {
+                if *max_executions == 0 {
+                    return Err(PolicyError::InvalidRule(
+                        "max executions must be at least 1".to_string(),
+                    ));
+                }
+                if !window_seconds.is_finite() || *window_seconds <= 0.0 {
+                    return Err(PolicyError::InvalidRule(format!(
+                        "window {} must be finite and positive",
+                        window_seconds
+                    )));
+                }
+            }
+            ExecutionRule::ActionAllowList(_) | ExecutionRule::MaxCumulativeSpend(_) => {}
+        }
+        Ok(())
+    }
+}
+
+/// What an execution looks like to the policy chain.
+pub struct PolicyContext<'a> {
+    pub authority: &'a AuthorityUnit,
+    pub action_name: &'a str,
+    pub action_scope: &'a str,
+    pub now: f64,
+}
+
+impl PolicyContext<'_> {
+    pub fn principal(&self) -> &str {
+        self.authority
+            .delegation_chain
+            .last()
+            .map(String::as_str)
+            .unwrap_or("")
+    }
+}
+
+/// One rule's decision, with the reason for it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuleEvaluation {
+    pub rule: String,
+    pub allowed: bool,
+    pub reason: String,
+}
+
+impl Canonical for RuleEvaluation {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("rule", CanonicalValue::str(&self.rule)),
+            ("allowed", CanonicalValue::Bool(self.allowed)),
+            ("reason", CanonicalValue::str(&self.reason)),
+        ])
+    }
+}
+
+#[derive(Debug, Default)]
+struct PolicyState {
+    /// Admission times, by rate limit rule and principal, within the rule's
+    /// window.
+    executions: HashMap<(String, String), VecDeque<f64>>,
+    /// Reserved and charged spend, by principal and unit.
+    spent: HashMap<(String, String), i64>,
+}
+
+impl PolicyState {
+    fn spent(&self, principal: &str, unit: &str) -> Price {
+        let amount = self
+            .spent
+            .get(&(principal.to_string(), unit.to_string()))
+            .copied()
+            .unwrap_or(0);
+        Price::new(amount, unit)
+    }
+
+    fn set_spent(&mut self, principal: &str, spent: Price) {
+        self.spent
+            .insert((principal.to_string(), spent.unit), spent.amount);
+    }
+}
+
+/// An ordered set of named execution rules plus the per-principal history
+/// the rate and spend rules need. Every rule is evaluated, so a denial lists
+/// all the rules that refused, not just the first.
+#[derive(Debug, Default)]
+pub struct PolicyChain {
+    rules: RwLock<Vec<(String, ExecutionRule)>>,
+    state: Mutex<PolicyState>,
+}
+
+impl PolicyChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(self, name: &str, rule: ExecutionRule) -> Result<Self, PolicyError> {
+        self.add_rule(name, rule)?;
+        Ok(self)
+    }
+
+    pub fn add_rule(&self, name: &str, rule: ExecutionRule) -> Result<(), PolicyError> {
+        rule.validate()?;
+        let mut rules = self.rules.write().map_err(|_| PolicyError::LockError)?;
+        if rules.iter().any(|(existing, _)| existing == name) {
+            return Err(PolicyError::DuplicateRule(name.to_string()));
+        }
+        rules.push((name.to_string(), rule));
+        Ok(())
+    }
+
+    pub fn remove_rule(&self, name: &str) -> Result<bool, PolicyError> {
+        let mut rules = self.rules.write().map_err(|_| PolicyError::LockError)?;
+        let before = rules.len();
+        rules.retain(|(existing, _)| existing != name);
+        self.state
+            .lock()
+            .map_err(|_| PolicyError::LockError)?
+            .executions
+            .retain(|(rule, _), _| rule != name);
+        Ok(rules.len() != before)
+    }
+
+    /// Evaluates every rule against `context`. If all allow, the execution
+    /// is counted towards the principal's window under each rate limit rule
+    /// and the unit's price is reserved against its spend, until the gate
+    /// releases or refunds them.
+    pub fn admit(&self, context: &PolicyContext) -> Result<Vec<RuleEvaluation>, PolicyError> {
+        let rules = self.rules.read().map_err(|_| PolicyError::LockError)?;
+        let mut state = self.state.lock().map_err(|_| PolicyError::LockError)?;
+        let principal = context.principal();
+        let evaluations: Vec<RuleEvaluation> = rules
+            .iter()
+            .map(|(name, rule)| {
+                let decision = Self::evaluate(name, rule, context, &mut state);
+                RuleEvaluation {
+                    rule: name.clone(),
+                    allowed: decision.is_ok(),
+                    reason: decision.unwrap_or_else(|reason| reason),
+                }
+            })
+            .collect();
+
+        if evaluations.iter().all(|evaluation| evaluation.allowed) {
+            for (name, rule) in rules.iter() {
+                if let ExecutionRule::RateLimit { .. } = rule {
+                    state
+                        .executions
+                        .entry((name.clone(), principal.to_string()))
+                        .or_default()
+                        .push_back(context.now);
+                }
+            }
+            let price = &context.authority.price;
+            if let Ok(total) = state.spent(principal, &price.unit).checked_add(price) {
+                state.set_spent(principal, total);
+            }
+        }
+        Ok(evaluations)
+    }
+
+    /// Undoes the admission at `admitted_at` of an execution that was rolled
+    /// back: it no longer counts towards the principal's rate windows, and
+    /// `amount` of its reserved spend is returned.
+    pub fn release(
+        &self,
+        principal: &str,
+        admitted_at: f64,
+        amount: &Price,
+    ) -> Result<(), PolicyError> {
+        {
+            let mut state = self.state.lock().map_err(|_| PolicyError::LockError)?;
+            for ((_, owner), recent) in state.executions.iter_mut() {
+                if owner == principal {
+                    if let Some(index) = recent.iter().rposition(|at| *at == admitted_at) {
+                        recent.remove(index);
+                    }
+                }
+            }
+        }
+        self.refund(principal, amount)
+    }
+
+    /// Returns `amount` of a principal's reserved spend, for an execution
+    /// charged less than its quote.
+    pub fn refund(&self, principal: &str, amount: &Price) -> Result<(), PolicyError> {
+        let mut state = self.state.lock().map_err(|_| PolicyError::LockError)?;
+        if let Ok(total) = state.spent(principal, &amount.unit).checked_sub(amount) {
+            state.set_spent(principal, total);
+        }
+        Ok(())
+    }
+
+    /// A principal's cumulative spend in `unit`.
+    pub fn spent(&self, principal: &str, unit: &str) -> Result<Price, PolicyError> {
+        let state = self.state.lock().map_err(|_| PolicyError::LockError)?;
+        Ok(state.spent(principal, unit))
+    }
+
+    fn evaluate(
+        name: &str,
+        rule: &ExecutionRule,
+        context: &PolicyContext,
+        state: &mut PolicyState,
+    ) -> Result<String, String> {
+        match rule {
+            ExecutionRule::TimeOfDay { start, end } => {
+                let second = context.now.rem_euclid(SECONDS_PER_DAY);
+                let (start, end) = (*start as f64, *end as f64);
+                let inside = if start <= end {
+                    second >= start && second < end
+                } else {
+                    second >= start || second < end
+                };
+                let reason = format!("second {:.0} of the day, window {}..{}", second, start, end);
+                if inside {
+                    Ok(reason)
+                } else {
+                    Err(reason)
+                }
+            }
+            ExecutionRule::RateLimit {
+                max_executions,
+                window_seconds,
+            } => {
+                let key = (name.to_string(), context.principal().to_string());
+                let count = match state.executions.get_mut(&key) {
+                    Some(recent) => {
+                        while recent
+                            .front()
+                            .is_some_and(|at| context.now - at >= *window_seconds)
+                        {
+                            recent.pop_front();
+                        }
+                        recent.len()
+                    }
+                    None => 0,
+                };
+                if count == 0 {
+                    state.executions.remove(&key);
+                }
+                let reason = format!(
+                    "{} of {} executions by '{}' in the last {}s",
+                    count,
+                    max_executions,
+                    context.principal(),
+                    window_seconds
+                );
+                if (count as u64) < *max_executions {
+                    Ok(reason)
+                } else {
+                    Err(reason)
+                }
+            }
+            ExecutionRule::ActionAllowList(actions) => {
+                if actions.iter().any(|action| action == context.action_name) {
+                    Ok(format!("action '{}' is allowed", context.action_name))
+                } else {
+                    Err(format!("action '{}' is not allowed", context.action_name))
+                }
+            }
+            ExecutionRule::MaxCumulativeSpend(max) => {
+                let price = &context.authority.price;
+                let projected = state
+                    .spent(context.principal(), &price.unit)
+                    .checked_add(price)
+                    .and_then(|projected| projected.checked_cmp(max).map(|cmp| (projected, cmp)))
+                    .map_err(|err| err.to_string())?;
+                let reason = format!(
+                    "'{}' would reach {} of {}",
+                    context.principal(),
+                    projected.0,
+                    max
+                );
+                match projected.1 {
+                    Ordering::Greater => Err(reason),
+                    _ => Ok(reason),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::core::canonical::{Canonical, CanonicalValue};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum PriceError {
+    #[error("cannot combine prices in '{left}' and '{right}'")]
+    UnitMismatch { left: String, right: String },
+    #[error("price arithmetic overflowed")]
+    Overflow,
+}
+
+/// An amount in a named unit: a currency code such as `USD` (in its minor
+/// unit), compute credits, risk points. Arithmetic and comparison are only
+/// defined between prices of the same unit. Plain integers convert to
+/// unitless prices.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "PriceRepr"))]
+pub struct Price {
+    pub amount: i64,
+    pub unit: String,
+}
+
+/// Accepts the pre-unit encoding, a bare integer, as a unitless price.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum PriceRepr {
+    Unitless(i64),
+    Priced { amount: i64, unit: String },
+}
+
+#[cfg(feature = "serde")]
+impl From<PriceRepr> for Price {
+    fn from(repr: PriceRepr) -> Self {
+        match repr {
+            PriceRepr::Unitless(amount) => Price::from(amount),
+            PriceRepr::Priced { amount, unit } => Price { amount, unit },
+        }
+    }
+}
+
+impl Price {
+    pub const UNITLESS: &'static str = "";
+
+    pub fn new(amount: i64, unit: &str) -> Self {
+        Price {
+            amount,
+            unit: unit.to_string(),
+        }
+    }
+
+    pub fn zero(unit: &str) -> Self {
+        Self::new(0, unit)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.amount < 0
+    }
+
+    /// The same unit with a different amount.
+    pub fn with_amount(&self, amount: i64) -> Self {
+        Self::new(amount, &self.unit)
+    }
+
+    pub fn ensure_same_unit(&self, other: &Price) -> Result<(), PriceError> {
+        if self.unit != other.unit {
+            return Err(PriceError::UnitMismatch {
+                left: self.unit.clone(),
+                right: other.unit.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn checked_add(&self, other: &Price) -> Result<Price, PriceError> {
+        self.ensure_same_unit(other)?;
+        self.amount
+            .checked_add(other.amount)
+            .map(|amount| self.with_amount(amount))
+            .ok_or(PriceError::Overflow)
+    }
+
+    pub fn checked_sub(&self, other: &Price) -> Result<Price, PriceError> {
+        self.ensure_same_unit(other)?;
+        self.amount
+            .checked_sub(other.amount)
+            .map(|amount| self.with_amount(amount))
+            .ok_or(PriceError::Overflow)
+    }
+
+    /// Orders two prices of the same unit.
+    pub fn checked_cmp(&self, other: &Price) -> Result<std::cmp::Ordering, PriceError> {
+        self.ensure_same_unit(other)?;
+        Ok(self.amount.cmp(&other.amount))
+    }
+
+    /// Sums prices that must all be in `unit`.
+    pub fn checked_sum<'a>(
+        unit: &str,
+        prices: impl IntoIterator<Item = &'a Price>,
+    ) -> Result<Price, PriceError> {
+        prices
+            .into_iter()
+            .try_fold(Price::zero(unit), |sum, price| sum.checked_add(price))
+    }
+}
+
+impl From<i64> for Price {
+    fn from(amount: i64) -> Self {
+        Price::new(amount, Price::UNITLESS)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.unit.is_empty() {
+            write!(f, "{}", self.amount)
+        } else {
+            write!(f, "{} {}", self.amount, self.unit)
+        }
+    }
+}
+
+impl Canonical for Price {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("amount", CanonicalValue::Int(self.amount)),
+            ("unit", CanonicalValue::str(&self.unit)),
+        ])
+    }
+}
@@ -0,0 +1,47 @@
+use crate::core::authority::AuthorityUnit;
+use crate::core::price::{Price, PriceError};
+use std::any::Any;
+use std::cmp::Ordering;
+
+/// What a pricer sees of a completed execution.
+pub struct PricingContext<'a> {
+    pub authority: &'a AuthorityUnit,
+    pub action_name: &'a str,
+    pub action_scope: &'a str,
+    /// The action's output as recorded in the trace.
+    pub result: &'a str,
+    /// The action's output itself, if its type offers it through
+    /// `TraceSummary::priced_output`.
+    pub output: Option<&'a dyn Any>,
+}
+
+impl PricingContext<'_> {
+    /// The typed output, if it is a `T`.
+    pub fn output<T: Any>(&self) -> Option<&T> {
+        self.output?.downcast_ref()
+    }
+}
+
+/// Computes the actual cost of an execution once the action has completed,
+/// e.g. from the rows it affected or the bytes it sent. The unit's price is
+/// the quote; the gate never charges more than that, see `bounded_charge`.
+pub trait Pricer: Send + Sync {
+    fn price(&self, context: &PricingContext) -> Price;
+}
+
+/// The amount charged for a computed price: floored at zero and capped at
+/// `quoted`. A price in a different unit than the quote cannot be compared
+/// with it and is refused.
+pub fn bounded_charge(quoted: &Price, computed: Price) -> Result<Price, PriceError> {
+    Ok(match computed.checked_cmp(quoted)? {
+        Ordering::Greater => quoted.clone(),
+        _ if computed.is_negative() => quoted.with_amount(0),
+        _ => computed,
+    })
+}
+
+impl<F: Fn(&PricingContext) -> Price + Send + Sync> Pricer for F {
+    fn price(&self, context: &PricingContext) -> Price {
+        self(context)
+    }
+}
@@ -0,0 +1,206 @@
+use crate::core::scope::Scope;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Mutex, RwLock};
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum RateLimitError {
+    #[error("invalid rate limit: {0}")]
+    InvalidLimit(String),
+    #[error("rate limit for {key} exceeded, retry after {retry_after:.3}s")]
+    Limited { key: RateLimitKey, retry_after: f64 },
+    #[error("internal lock error")]
+    LockError,
+}
+
+/// What a token bucket is keyed by.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RateLimitKey {
+    /// Executions by any unit whose delegation chain includes the principal,
+    /// so a limit on a delegator also caps everything it delegated.
+    Principal(String),
+    /// Executions whose action scope falls within the scope pattern.
+    Scope(String),
+}
+
+impl fmt::Display for RateLimitKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimitKey::Principal(principal) => write!(f, "principal '{}'", principal),
+            RateLimitKey::Scope(scope) => write!(f, "scope '{}'", scope),
+        }
+    }
+}
+
+/// A token bucket holding up to `capacity` executions, refilled
+/// continuously at `refill_per_second`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub refill_per_second: f64,
+}
+
+impl RateLimit {
+    pub fn new(capacity: u32, refill_per_second: f64) -> Result<Self, RateLimitError> {
+        let limit = RateLimit {
+            capacity,
+            refill_per_second,
+        };
+        limit.validate()?;
+        Ok(limit)
+    }
+
+    pub fn validate(&self) -> Result<(), RateLimitError> {
+        if self.capacity == 0 {
+            return Err(RateLimitError::InvalidLimit(
+                "capacity must be at least 1".to_string(),
+            ));
+        }
+        if !self.refill_per_second.is_finite() || self.refill_per_second < 0.0 {
+            return Err(RateLimitError::InvalidLimit(format!(
+                "refill rate {} must be finite and non-negative",
+                self.refill_per_second
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: f64,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: f64) {
+        let elapsed = (now - self.updated_at).max(0.0);
+        self.tokens = (self.tokens + elapsed * limit.refill_per_second).min(limit.capacity as f64);
+        self.updated_at = self.updated_at.max(now);
+    }
+}
+
+/// Token-bucket limits keyed by principal and by action scope. Limits can
+/// be set and removed while a gate is using the limiter; a bucket keeps its
+/// tokens, up to the new capacity, when its limit changes.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limits: RwLock<BTreeMap<RateLimitKey, RateLimit>>,
+    buckets: Mutex<BTreeMap<RateLimitKey, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limit(self, key: RateLimitKey, limit: RateLimit) -> Result<Self, RateLimitError> {
+        self.set_limit(key, limit)?;
+        Ok(self)
+    }
+
+    pub fn set_limit(&self, key: RateLimitKey, limit: RateLimit) -> Result<(), RateLimitError> {
+        limit.validate()?;
+        if let RateLimitKey::Scope(pattern) = &key {
+            Scope::parse(pattern).map_err(|err| RateLimitError::InvalidLimit(err.to_string()))?;
+        }
+        self.limits
+            .write()
+            .map_err(|_| RateLimitError::LockError)?
+            .insert(key, limit);
+        Ok(())
+    }
+
+    pub fn remove_limit(&self, key: &RateLimitKey) -> Result<bool, RateLimitError> {
+        let removed = self
+            .limits
+            .write()
+            .map_err(|_| RateLimitError::LockError)?
+            .remove(key)
+            .is_some();
+        self.buckets
+            .lock()
+            .map_err(|_| RateLimitError::LockError)?
+            .remove(key);
+        Ok(removed)
+    }
+
+    /// Takes one token from every bucket that applies to an execution by
+    /// `delegation_chain` in `action_scope`. Nothing is taken unless every
+    /// bucket has a token to give.
+    pub fn acquire(
+        &self,
+        delegation_chain: &[String],
+        action_scope: &str,
+        now: f64,
+    ) -> Result<(), RateLimitError> {
+        let limits = self.limits.read().map_err(|_| RateLimitError::LockError)?;
+        let mut buckets = self.buckets.lock().map_err(|_| RateLimitError::LockError)?;
+        let applicable = Self::applicable(&limits, delegation_chain, action_scope);
+
+        for (key, limit) in &applicable {
+            let bucket = buckets.entry((*key).clone()).or_insert(Bucket {
+                tokens: limit.capacity as f64,
+                updated_at: now,
+            });
+            bucket.refill(limit, now);
+            if bucket.tokens < 1.0 {
+                let retry_after = if limit.refill_per_second > 0.0 {
+                    (1.0 - bucket.tokens) / limit.refill_per_second
+                } else {
+                    f64::INFINITY
+                };
+                return Err(RateLimitError::Limited {
+                    key: (*key).clone(),
+                    retry_after,
+                });
+            }
+        }
+        for (key, _) in &applicable {
+            if let Some(bucket) = buckets.get_mut(*key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives back the token `acquire` took for an execution that was rolled
+    /// back, up to each bucket's capacity.
+    pub fn release(
+        &self,
+        delegation_chain: &[String],
+        action_scope: &str,
+        now: f64,
+    ) -> Result<(), RateLimitError> {
+        let limits = self.limits.read().map_err(|_| RateLimitError::LockError)?;
+        let mut buckets = self.buckets.lock().map_err(|_| RateLimitError::LockError)?;
+        for (key, limit) in Self::applicable(&limits, delegation_chain, action_scope) {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.refill(limit, now);
+                bucket.tokens = (bucket.tokens + 1.0).min(limit.capacity as f64);
+            }
+        }
+        Ok(())
+    }
+
+    fn applicable<'a>(
+        limits: &'a BTreeMap<RateLimitKey, RateLimit>,
+        delegation_chain: &[String],
+        action_scope: &str,
+    ) -> Vec<(&'a RateLimitKey, &'a RateLimit)> {
+        let scope = Scope::parse(action_scope).ok();
+        limits
+            .iter()
+            .filter(|(key, _)| match key {
+                RateLimitKey::Principal(principal) => delegation_chain.contains(principal),
+                RateLimitKey::Scope(pattern) => scope.as_ref().is_some_and(|scope| {
+                    Scope::parse(pattern).is_ok_and(|pattern| scope.is_subset_of(&pattern))
+                }),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,478 @@
+//! Deterministic replay of recorded authority state and gate decisions.
+//!
+//! A replay feeds the recorded events through a fresh `AuthorityManager` and
+//! `ExecutionGate` whose clock is set to each event's timestamp, then
+//! compares the traces the gate emits with the traces that were recorded.
+//! Any difference means the recorded decision did not follow from the
+//! recorded authority state.
+
+use crate::core::authority::AuthorityUnit;
+use crate::core::canonical::{Canonical, CanonicalValue};
+use crate::core::clock::{Clock, ManualClock};
+use crate::core::gate::{ExecutionGate, ExecutionGateError, ReservationCompensation};
+use crate::core::issuance::IssuanceRule;
+use crate::core::manager::{AuthorityManager, ManagerError};
+use crate::core::policy::{ExecutionRule, PolicyChain, PolicyError};
+use crate::core::price::Price;
+use crate::core::pricing::Pricer;
+use crate::core::ratelimit::{RateLimit, RateLimitError, RateLimitKey, RateLimiter};
+use crate::core::sink::MemoryTraceSink;
+use crate::core::trace::{DecisionTrace, IdStrategy, TraceLog, TraceLogError};
+use ed25519_dalek::VerifyingKey;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone)]
+pub enum ReplayError {
+    #[error("replay manager setup failed: {0}")]
+    Setup(ManagerError),
+    #[error("replay event {index} was rejected by the manager: {source}")]
+    Manager { index: usize, source: ManagerError },
+    #[error("replay gate error: {0}")]
+    Gate(#[from] ExecutionGateError),
+    #[error("replay policy setup failed: {0}")]
+    Policy(#[from] PolicyError),
+    #[error("replay rate limit setup failed: {0}")]
+    RateLimit(#[from] RateLimitError),
+}
+
+/// An execution request as presented to the gate, with the output the
+/// action produced at the time and, if it registered a compensation, that
+/// compensation's output. Neither is re-run; the recorded outputs stand in
+/// for them.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExecutionRequest {
+    pub timestamp: f64,
+    pub authority: AuthorityUnit,
+    pub action_name: String,
+    pub action_scope: String,
+    pub action_output: Result<String, String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub compensation_output: Option<Result<String, String>>,
+}
+
+/// One recorded input, in the order it happened.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ReplayEvent {
+    Issue {
+        timestamp: f64,
+        authority: AuthorityUnit,
+    },
+    RevokeAuthority {
+        timestamp: f64,
+        authority_id: String,
+        reason: String,
+    },
+    RevokePrincipal {
+        timestamp: f64,
+        principal: String,
+        reason: String,
+    },
+    Execute(ExecutionRequest),
+    /// A reservation taken under its recorded ID, with the output its
+    /// compensation gave if it was aborted or expired.
+    Reserve {
+        timestamp: f64,
+        reservation_id: String,
+        authority: AuthorityUnit,
+        action_name: String,
+        action_scope: String,
+        #[cfg_attr(feature = "serde", serde(default))]
+        compensation_output: Option<Result<String, String>>,
+    },
+    CommitReservation {
+        timestamp: f64,
+        reservation_id: String,
+        output: String,
+    },
+    AbortReservation {
+        timestamp: f64,
+        reservation_id: String,
+        reason: String,
+    },
+    /// An explicit `release_expired` sweep. Sweeps run by admissions and
+    /// settlements are reproduced by those events.
+    ReleaseExpired {
+        timestamp: f64,
+    },
+}
+
+impl ReplayEvent {
+    pub fn timestamp(&self) -> f64 {
+        match self {
+            ReplayEvent::Issue { timestamp, .. }
+            | ReplayEvent::RevokeAuthority { timestamp, .. }
+            | ReplayEvent::RevokePrincipal { timestamp, .. }
+            | ReplayEvent::Reserve { timestamp, .. }
+            | ReplayEvent::CommitReservation { timestamp, .. }
+            | ReplayEvent::AbortReservation { timestamp, .. }
+            | ReplayEvent::ReleaseExpired { timestamp } => *timestamp,
+            ReplayEvent::Execute(request) => request.timestamp,
+        }
+    }
+}
+
+/// A difference between a recorded trace and its replayed counterpart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    /// The traces at `index` disagree on `field`.
+    Field {
+        index: usize,
+        field: &'static str,
+        recorded: String,
+        replayed: String,
+    },
+    /// The recorded trace at `index` was not reproduced.
+    Missing { index: usize },
+    /// The replay produced a trace at `index` that was never recorded.
+    Unexpected { index: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayReport {
+    pub replayed: Vec<DecisionTrace>,
+    pub divergences: Vec<Divergence>,
+    /// Set if the recorded traces are not linked into a hash chain. This is
+    /// `TraceLog::verify_links`; check the recording against its anchored
+    /// head with `verify_head` as well.
+    pub chain_error: Option<TraceLogError>,
+}
+
+impl ReplayReport {
+    /// Whether every recorded decision was reproduced from the recorded
+    /// authority state.
+    pub fn is_consistent(&self) -> bool {
+        self.divergences.is_empty() && self.chain_error.is_none()
+    }
+}
+
+/// Re-runs manager and gate decisions under the recorded clock. Trust
+/// configuration, issuance rules, execution rules, rate limits, the pricer
+/// and the reservation timeout are supplied up front and applied to the
+/// fresh manager and gate of every replay.
+pub struct Replayer {
+    max_age_seconds: i64,
+    id_strategy: IdStrategy,
+    trusted_issuers: Vec<(String, VerifyingKey)>,
+    delegators: Vec<(String, VerifyingKey)>,
+    root_authorities: Vec<(String, String, Price)>,
+    issuance_rules: Vec<(String, IssuanceRule)>,
+    execution_rules: Vec<(String, ExecutionRule)>,
+    rate_limits: Vec<(RateLimitKey, RateLimit)>,
+    pricer: Option<Arc<dyn Pricer>>,
+    reservation_timeout: Option<f64>,
+}
+
+impl Replayer {
+    pub fn new(max_age_seconds: i64) -> Self {
+        Replayer {
+            max_age_seconds,
+            id_strategy: IdStrategy::Random,
+            trusted_issuers: Vec::new(),
+            delegators: Vec::new(),
+            root_authorities: Vec::new(),
+            issuance_rules: Vec::new(),
+            execution_rules: Vec::new(),
+            rate_limits: Vec::new(),
+            pricer: None,
+            reservation_timeout: None,
+        }
+    }
+
+    /// Replays with the ID strategy the recording gate used. With
+    /// `IdStrategy::ContentDerived`, trace IDs and chain links are compared
+    /// as well.
+    pub fn with_id_strategy(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    pub fn trust_issuer(mut self, issuer: &str, key: VerifyingKey) -> Self {
+        self.trusted_issuers.push((issuer.to_string(), key));
+        self
+    }
+
+    pub fn register_delegator(mut self, principal: &str, key: VerifyingKey) -> Self {
+        self.delegators.push((principal.to_string(), key));
+        self
+    }
+
+    pub fn grant_root_authority(
+        mut self,
+        issuer: &str,
+        scope: &str,
+        price: impl Into<Price>,
+    ) -> Self {
+        self.root_authorities
+            .push((issuer.to_string(), scope.to_string(), price.into()));
+        self
+    }
+
+    /// Replays with the recording manager's issuance rules, which it also
+    /// applied to signed units issued elsewhere.
+    pub fn with_issuance_rule(mut self, name: &str, rule: IssuanceRule) -> Self {
+        self.issuance_rules.push((name.to_string(), rule));
+        self
+    }
+
+    /// Replays with a policy chain holding the recording gate's rules, in
+    /// the same order. Each trace's policy evaluation is compared as well.
+    pub fn with_execution_rule(mut self, name: &str, rule: ExecutionRule) -> Self {
+        self.execution_rules.push((name.to_string(), rule));
+        self
+    }
+
+    /// Replays with the recording gate's rate limits, starting from full
+    /// buckets.
+    pub fn with_rate_limit(mut self, key: RateLimitKey, limit: RateLimit) -> Self {
+        self.rate_limits.push((key, limit));
+        self
+    }
+
+    /// Replays with the recording gate's pricer, which prices each replayed
+    /// execution from its recorded output.
+    pub fn with_pricer(mut self, pricer: Arc<dyn Pricer>) -> Self {
+        self.pricer = Some(pricer);
+        self
+    }
+
+    /// Replays with the recording gate's reservation timeout, so
+    /// reservations expire at the recorded times.
+    pub fn with_reservation_timeout(mut self, seconds: f64) -> Self {
+        self.reservation_timeout = Some(seconds);
+        self
+    }
+
+    pub fn replay(
+        &self,
+        events: &[ReplayEvent],
+        recorded: &[DecisionTrace],
+    ) -> Result<ReplayReport, ReplayError> {
+        let clock = Arc::new(ManualClock::new(
+            events.first().map(ReplayEvent::timestamp).unwrap_or(0.0),
+        ));
+        let manager = Arc::new(self.manager(clock.clone())?);
+        let sink = Arc::new(MemoryTraceSink::new());
+        let validator = {
+            let manager = manager.clone();
+            move |au: &AuthorityUnit| manager.validate_authority(au)
+        };
+        let mut gate = ExecutionGate::new(validator)
+            .with_clock(clock.clone())
+            .with_id_strategy(self.id_strategy)
+            .with_sink(sink.clone())?;
+        if !self.execution_rules.is_empty() {
+            let policy = PolicyChain::new();
+            for (name, rule) in &self.execution_rules {
+                policy.add_rule(name, rule.clone())?;
+            }
+            gate = gate.with_policy(Arc::new(policy));
+        }
+        if !self.rate_limits.is_empty() {
+            let limiter = RateLimiter::new();
+            for (key, limit) in &self.rate_limits {
+                limiter.set_limit(key.clone(), *limit)?;
+            }
+            gate = gate.with_rate_limiter(Arc::new(limiter));
+        }
+        if let Some(pricer) = &self.pricer {
+            gate = gate.with_pricer(pricer.clone());
+        }
+        if let Some(seconds) = self.reservation_timeout {
+            gate = gate.with_reservation_timeout(seconds)?;
+        }
+
+        for (index, event) in events.iter().enumerate() {
+            clock.set(event.timestamp());
+            let applied = match event {
+                ReplayEvent::Issue { authority, .. } => manager.issue_authority(authority.clone()),
+                ReplayEvent::RevokeAuthority {
+                    authority_id,
+                    reason,
+                    ..
+                } => manager.revoke_authority(authority_id, reason).map(drop),
+                ReplayEvent::RevokePrincipal {
+                    principal, reason, ..
+                } => manager.revoke_principal(principal, reason).map(drop),
+                ReplayEvent::Execute(request) => {
+                    // Refusals and failures are captured as traces.
+                    let action = || request.action_output.clone();
+                    let _ = match &request.compensation_output {
+                        Some(output) => gate.execute_with_compensation(
+                            &request.authority,
+                            &action,
+                            &|_| output.clone(),
+                            &request.action_name,
+                            &request.action_scope,
+                        ),
+                        None => gate.execute_with_authority(
+                            &request.authority,
+                            &action,
+                            &request.action_name,
+                            &request.action_scope,
+                        ),
+                    };
+                    Ok(())
+                }
+                // Refusals are captured as traces, and settling an unknown
+                // or closed reservation records nothing.
+                ReplayEvent::Reserve {
+                    reservation_id,
+                    authority,
+                    action_name,
+                    action_scope,
+                    compensation_output,
+                    ..
+                } => {
+                    let compensation = compensation_output.clone().map(|output| {
+                        Arc::new(move |_: &str| output.clone()) as ReservationCompensation
+                    });
+                    let _ = gate.reserve_as(
+                        reservation_id,
+                        authority,
+                        compensation,
+                        action_name,
+                        action_scope,
+                    );
+                    Ok(())
+                }
+                ReplayEvent::CommitReservation {
+                    reservation_id,
+                    output,
+                    ..
+                } => {
+                    let _ = gate.commit_reservation(reservation_id, output.clone());
+                    Ok(())
+                }
+                ReplayEvent::AbortReservation {
+                    reservation_id,
+                    reason,
+                    ..
+                } => {
+                    let _ = gate.abort_reservation(reservation_id, reason);
+                    Ok(())
+                }
+                ReplayEvent::ReleaseExpired { .. } => {
+                    let _ = gate.release_expired();
+                    Ok(())
+                }
+            };
+            applied.map_err(|source| ReplayError::Manager { index, source })?;
+        }
+
+        let replayed = sink
+            .trace_log()
+            .map_err(ExecutionGateError::from)?
+            .entries()
+            .to_vec();
+        let divergences = self.compare(recorded, &replayed);
+        let chain_error = TraceLog::from_entries(recorded.to_vec())
+            .verify_links()
+            .err();
+        Ok(ReplayReport {
+            replayed,
+            divergences,
+            chain_error,
+        })
+    }
+
+    fn manager(&self, clock: Arc<dyn Clock>) -> Result<AuthorityManager, ReplayError> {
+        let manager = AuthorityManager::with_max_age(self.max_age_seconds).with_clock(clock);
+        for (issuer, key) in &self.trusted_issuers {
+            manager
+                .trust_issuer(issuer, *key)
+                .map_err(ReplayError::Setup)?;
+        }
+        for (principal, key) in &self.delegators {
+            manager
+                .register_delegator(principal, *key)
+                .map_err(ReplayError::Setup)?;
+        }
+        for (issuer, scope, price) in &self.root_authorities {
+            manager
+                .grant_root_authority(issuer, scope, price.clone())
+                .map_err(ReplayError::Setup)?;
+        }
+        for (name, rule) in &self.issuance_rules {
+            manager
+                .add_issuance_rule(name, rule.clone())
+                .map_err(ReplayError::Setup)?;
+        }
+        Ok(manager)
+    }
+
+    fn compare(&self, recorded: &[DecisionTrace], replayed: &[DecisionTrace]) -> Vec<Divergence> {
+        let mut divergences = Vec::new();
+        for (index, (old, new)) in recorded.iter().zip(replayed).enumerate() {
+            let mut fields = vec![
+                (
+                    "sequence",
+                    old.sequence.to_string(),
+                    new.sequence.to_string(),
+                ),
+                (
+                    "action_name",
+                    old.action_name.clone(),
+                    new.action_name.clone(),
+                ),
+                (
+                    "authority_id",
+                    old.authority_id.clone(),
+                    new.authority_id.clone(),
+                ),
+                (
+                    "timestamp",
+                    old.timestamp.to_string(),
+                    new.timestamp.to_string(),
+                ),
+                (
+                    "outcome",
+                    old.outcome.as_str().into(),
+                    new.outcome.as_str().into(),
+                ),
+                (
+                    "error_kind",
+                    old.error_kind.clone().unwrap_or_default(),
+                    new.error_kind.clone().unwrap_or_default(),
+                ),
+                ("result", old.result.clone(), new.result.clone()),
+                ("policy", policy_json(old), policy_json(new)),
+            ];
+            if self.id_strategy == IdStrategy::ContentDerived {
+                fields.push(("id", old.id.clone(), new.id.clone()));
+                fields.push((
+                    "prev_hash",
+                    old.prev_hash.clone().unwrap_or_default(),
+                    new.prev_hash.clone().unwrap_or_default(),
+                ));
+            }
+            divergences.extend(fields.into_iter().filter(|(_, a, b)| a != b).map(
+                |(field, recorded, replayed)| Divergence::Field {
+                    index,
+                    field,
+                    recorded,
+                    replayed,
+                },
+            ));
+        }
+        divergences
+            .extend((replayed.len()..recorded.len()).map(|index| Divergence::Missing { index }));
+        divergences
+            .extend((recorded.len()..replayed.len()).map(|index| Divergence::Unexpected { index }));
+        divergences
+    }
+}
+
+fn policy_json(trace: &DecisionTrace) -> String {
+    CanonicalValue::Array(
+        trace
+            .policy
+            .iter()
+            .map(Canonical::canonical_value)
+            .collect(),
+    )
+    .to_json()
+}
@@ -0,0 +1,57 @@
+use crate::core::authority::AuthorityUnit;
+use std::collections::HashMap;
+
+/// Revoked authority unit IDs and principals, each with the reason given.
+/// Revoking a principal invalidates every unit whose delegation chain names
+/// it, including units issued later.
+#[derive(Debug, Clone, Default)]
+pub struct RevocationList {
+    authorities: HashMap<String, String>,
+    principals: HashMap<String, String>,
+}
+
+impl RevocationList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `false` if the unit was already revoked.
+    pub fn revoke_authority(&mut self, au_id: &str, reason: &str) -> bool {
+        if self.authorities.contains_key(au_id) {
+            return false;
+        }
+        self.authorities
+            .insert(au_id.to_string(), reason.to_string());
+        true
+    }
+
+    /// Returns `false` if the principal was already revoked.
+    pub fn revoke_principal(&mut self, principal: &str, reason: &str) -> bool {
+        if self.principals.contains_key(principal) {
+            return false;
+        }
+        self.principals
+            .insert(principal.to_string(), reason.to_string());
+        true
+    }
+
+    pub fn is_authority_revoked(&self, au_id: &str) -> bool {
+        self.authorities.contains_key(au_id)
+    }
+
+    pub fn is_principal_revoked(&self, principal: &str) -> bool {
+        self.principals.contains_key(principal)
+    }
+
+    /// The reason `au` is revoked, whether directly or through a revoked
+    /// principal in its delegation chain.
+    pub fn revocation_reason(&self, au: &AuthorityUnit) -> Option<&str> {
+        if let Some(reason) = self.authorities.get(&au.id) {
+            return Some(reason);
+        }
+        au.delegation_chain
+            .iter()
+            .find_map(|principal| self.principals.get(principal))
+            .map(String::as_str)
+    }
+}
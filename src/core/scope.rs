@@ -0,0 +1,155 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ScopeError {
+    #[error("scope must be provided")]
+    Empty,
+    #[error("scope '{0}' contains an empty segment")]
+    EmptySegment(String),
+}
+
+/// A hierarchical authority scope such as `db:orders:read`,
+/// `fs:/data/**:write` or `payments:*`.
+///
+/// Scopes are `:`-separated segments. A segment that is exactly `*` (or
+/// `**`) matches any one segment, and when it is the last segment it matches
+/// one or more trailing segments. Inside a segment, `*` matches any run of
+/// characters except `/` and `**` matches any run of characters. The legacy
+/// scope `any` is an alias for `*`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Scope {
+    raw: String,
+    segments: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Literal(char),
+    Star,
+    DoubleStar,
+}
+
+impl Scope {
+    pub fn parse(scope: &str) -> Result<Self, ScopeError> {
+        if scope.is_empty() {
+            return Err(ScopeError::Empty);
+        }
+        let segments: Vec<String> = if scope == "any" {
+            vec!["*".to_string()]
+        } else {
+            scope.split(':').map(str::to_string).collect()
+        };
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err(ScopeError::EmptySegment(scope.to_string()));
+        }
+        Ok(Scope {
+            raw: scope.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    /// Whether every concrete scope matched by `self` is also matched by
+    /// `other`. A concrete scope is a subset of a pattern exactly when the
+    /// pattern matches it.
+    pub fn is_subset_of(&self, other: &Scope) -> bool {
+        let ours = &self.segments;
+        let theirs = &other.segments;
+        if is_wildcard_segment(&theirs[theirs.len() - 1]) {
+            let fixed = theirs.len() - 1;
+            return ours.len() > fixed
+                && ours[..fixed]
+                    .iter()
+                    .zip(&theirs[..fixed])
+                    .all(|(a, b)| segment_subset(a, b));
+        }
+        if is_wildcard_segment(&ours[ours.len() - 1]) || ours.len() != theirs.len() {
+            return false;
+        }
+        ours.iter().zip(theirs).all(|(a, b)| segment_subset(a, b))
+    }
+
+    pub fn covers(&self, other: &Scope) -> bool {
+        other.is_subset_of(self)
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ScopeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::parse(s)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+fn is_wildcard_segment(segment: &str) -> bool {
+    segment == "*" || segment == "**"
+}
+
+fn tokenize(segment: &str) -> Vec<Token> {
+    if is_wildcard_segment(segment) {
+        return vec![Token::DoubleStar];
+    }
+    let mut tokens = Vec::new();
+    let mut chars = segment.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '*' {
+            if chars.peek() == Some(&'*') {
+                chars.next();
+                tokens.push(Token::DoubleStar);
+            } else {
+                tokens.push(Token::Star);
+            }
+        } else {
+            tokens.push(Token::Literal(c));
+        }
+    }
+    tokens
+}
+
+/// Whether segment pattern `b` matches every string matched by segment
+/// pattern `a`, treating `a`'s wildcards as opaque tokens that only an equal
+/// or wider wildcard in `b` can absorb.
+fn segment_subset(a: &str, b: &str) -> bool {
+    if is_wildcard_segment(b) {
+        return true;
+    }
+    let a = tokenize(a);
+    let b = tokenize(b);
+    // matches[i][j]: b[j..] absorbs a[i..]
+    let mut matches = vec![vec![false; b.len() + 1]; a.len() + 1];
+    matches[a.len()][b.len()] = true;
+    for i in (0..=a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            matches[i][j] = match b[j] {
+                Token::DoubleStar => matches[i][j + 1] || (i < a.len() && matches[i + 1][j]),
+                Token::Star => {
+                    matches[i][j + 1]
+                        || (i < a.len() && matches!(a[i], Token::Star) && matches[i + 1][j])
+                        || (i < a.len()
+                            && matches!(a[i], Token::Literal(c) if c != '/')
+                            && matches[i + 1][j])
+                }
+                Token::Literal(c) => {
+                    i < a.len() && a[i] == Token::Literal(c) && matches[i + 1][j + 1]
+                }
+            };
+        }
+    }
+    matches[0][0]
+}
@@ -0,0 +1,73 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum SignatureError {
+    #[error("authority unit is not signed")]
+    MissingSignature,
+    #[error("principal '{0}' has no trusted key")]
+    UntrustedPrincipal(String),
+    #[error("signature encoding is malformed")]
+    MalformedSignature,
+    #[error("signature verification failed for principal '{0}'")]
+    VerificationFailed(String),
+}
+
+/// An Ed25519 signature over an authority unit's canonical hash, tagged with
+/// the issuer whose key produced it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IssuerSignature {
+    pub issuer: String,
+    pub signature: String,
+}
+
+/// Trusted public keys indexed by principal name.
+#[derive(Debug, Clone, Default)]
+pub struct KeyRegistry {
+    keys: HashMap<String, VerifyingKey>,
+}
+
+impl KeyRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, principal: &str, key: VerifyingKey) -> Option<VerifyingKey> {
+        self.keys.insert(principal.to_string(), key)
+    }
+
+    pub fn remove(&mut self, principal: &str) -> Option<VerifyingKey> {
+        self.keys.remove(principal)
+    }
+
+    pub fn get(&self, principal: &str) -> Option<&VerifyingKey> {
+        self.keys.get(principal)
+    }
+
+    pub fn contains(&self, principal: &str) -> bool {
+        self.keys.contains_key(principal)
+    }
+
+    pub fn verify(
+        &self,
+        principal: &str,
+        message: &[u8],
+        signature_hex: &str,
+    ) -> Result<(), SignatureError> {
+        let key = self
+            .keys
+            .get(principal)
+            .ok_or_else(|| SignatureError::UntrustedPrincipal(principal.to_string()))?;
+        let bytes = hex::decode(signature_hex).map_err(|_| SignatureError::MalformedSignature)?;
+        let signature =
+            Signature::from_slice(&bytes).map_err(|_| SignatureError::MalformedSignature)?;
+        key.verify(message, &signature)
+            .map_err(|_| SignatureError::VerificationFailed(principal.to_string()))
+    }
+}
+
+pub fn sign_message(key: &SigningKey, message: &[u8]) -> String {
+    hex::encode(key.sign(message).to_bytes())
+}
@@ -0,0 +1,309 @@
+use crate::core::canonical::{Canonical, CanonicalValue};
+use crate::core::trace::{DecisionTrace, LiabilityRecord, TraceLog};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone)]
+pub enum SinkError {
+    #[error("trace sink I/O error: {0}")]
+    Io(String),
+    #[error("trace sink is closed")]
+    Closed,
+    #[error("trace sink rejected record: {0}")]
+    Rejected(String),
+    #[error("internal lock error")]
+    LockError,
+}
+
+impl From<std::io::Error> for SinkError {
+    fn from(err: std::io::Error) -> Self {
+        SinkError::Io(err.to_string())
+    }
+}
+
+/// Where a trace chain left off: the sequence number of the next trace and
+/// the hash of the last one written.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChainPosition {
+    pub next_sequence: u64,
+    pub head: Option<String>,
+}
+
+/// Destination for every trace the gate emits. An action only runs once its
+/// admitted trace is written; if a later write fails, the gate returns the
+/// error and the execution stands. Liability records accompany executed
+/// traces only.
+pub trait TraceSink: Send + Sync {
+    fn write(
+        &self,
+        trace: &DecisionTrace,
+        liability: Option<&LiabilityRecord>,
+    ) -> Result<(), SinkError>;
+
+    /// The chain position of traces already persisted by this sink, so a
+    /// restarted gate continues the same chain.
+    fn resume(&self) -> Result<ChainPosition, SinkError> {
+        Ok(ChainPosition::default())
+    }
+}
+
+/// A trace together with the liability record emitted alongside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SinkRecord {
+    pub trace: DecisionTrace,
+    pub liability: Option<LiabilityRecord>,
+}
+
+impl Canonical for SinkRecord {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("trace", self.trace.canonical_value()),
+            (
+                "liability",
+                self.liability
+                    .as_ref()
+                    .map(Canonical::canonical_value)
+                    .unwrap_or(CanonicalValue::Null),
+            ),
+            ("trace_hash", CanonicalValue::str(&self.trace.hash())),
+        ])
+    }
+}
+
+/// Keeps every record in memory.
+#[derive(Debug, Default)]
+pub struct MemoryTraceSink {
+    records: Mutex<Vec<SinkRecord>>,
+}
+
+impl MemoryTraceSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> Result<Vec<SinkRecord>, SinkError> {
+        self.records
+            .lock()
+            .map(|records| records.clone())
+            .map_err(|_| SinkError::LockError)
+    }
+
+    pub fn trace_log(&self) -> Result<TraceLog, SinkError> {
+        let records = self.records()?;
+        Ok(TraceLog::from_entries(
+            records.into_iter().map(|record| record.trace).collect(),
+        ))
+    }
+
+    pub fn liabilities(&self) -> Result<Vec<LiabilityRecord>, SinkError> {
+        let records = self.records()?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| record.liability)
+            .collect())
+    }
+}
+
+impl TraceSink for MemoryTraceSink {
+    fn write(
+        &self,
+        trace: &DecisionTrace,
+        liability: Option<&LiabilityRecord>,
+    ) -> Result<(), SinkError> {
+        self.records
+            .lock()
+            .map_err(|_| SinkError::LockError)?
+            .push(SinkRecord {
+                trace: trace.clone(),
+                liability: liability.cloned(),
+            });
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<ChainPosition, SinkError> {
+        let records = self.records.lock().map_err(|_| SinkError::LockError)?;
+        Ok(ChainPosition {
+            next_sequence: records.len() as u64,
+            head: records.last().map(|record| record.trace.hash()),
+        })
+    }
+}
+
+struct FileState {
+    file: File,
+    /// Length of the file up to its last complete line.
+    len: u64,
+    position: ChainPosition,
+}
+
+/// Appends one canonical JSON `SinkRecord` per line and fsyncs each write.
+/// Every line ends with the trace hash, which is how the chain is resumed
+/// when the file is reopened. A line torn by a failed write is truncated
+/// straight away.
+pub struct JsonlFileSink {
+    path: PathBuf,
+    state: Mutex<FileState>,
+}
+
+impl JsonlFileSink {
+    const HASH_SUFFIX_LEN: usize = 64 + r#""}"#.len();
+    const HASH_KEY: &'static str = r#","trace_hash":""#;
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SinkError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        // A torn final line was never acknowledged to the gate.
+        let complete = contents.rfind('\n').map(|end| end + 1).unwrap_or(0);
+        if complete < contents.len() {
+            file.set_len(complete as u64)?;
+            file.sync_all()?;
+        }
+
+        let mut position = ChainPosition::default();
+        for line in contents[..complete].lines() {
+            position.next_sequence += 1;
+            position.head = Some(Self::line_hash(line)?);
+        }
+
+        Ok(JsonlFileSink {
+            path,
+            state: Mutex::new(FileState {
+                file,
+                len: complete as u64,
+                position,
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn line_hash(line: &str) -> Result<String, SinkError> {
+        let split = line
+            .len()
+            .checked_sub(Self::HASH_SUFFIX_LEN)
+            .filter(|split| line.is_char_boundary(*split))
+            .ok_or_else(|| SinkError::Rejected("truncated trace line".to_string()))?;
+        if !line[..split].ends_with(Self::HASH_KEY) {
+            return Err(SinkError::Rejected("trace line has no hash".to_string()));
+        }
+        Ok(line[split..split + 64].to_string())
+    }
+}
+
+impl TraceSink for JsonlFileSink {
+    fn write(
+        &self,
+        trace: &DecisionTrace,
+        liability: Option<&LiabilityRecord>,
+    ) -> Result<(), SinkError> {
+        let record = SinkRecord {
+            trace: trace.clone(),
+            liability: liability.cloned(),
+        };
+        let mut line = record.canonical_json();
+        line.push('\n');
+
+        let mut guard = self.state.lock().map_err(|_| SinkError::LockError)?;
+        let state = &mut *guard;
+        // Cut back a torn line whose truncation failed after an earlier write.
+        if state.file.metadata()?.len() != state.len {
+            state.file.set_len(state.len)?;
+            state.file.sync_data()?;
+        }
+        let written = state
+            .file
+            .write_all(line.as_bytes())
+            .and_then(|()| state.file.sync_data());
+        if let Err(err) = written {
+            let _ = state
+                .file
+                .set_len(state.len)
+                .and_then(|()| state.file.sync_data());
+            return Err(err.into());
+        }
+        state.len += line.len() as u64;
+        state.position.next_sequence += 1;
+        state.position.head = Some(trace.hash());
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<ChainPosition, SinkError> {
+        let guard = self.state.lock().map_err(|_| SinkError::LockError)?;
+        Ok(guard.position.clone())
+    }
+}
+
+/// Forwards records over an mpsc channel. A disconnected receiver fails the
+/// write.
+pub struct ChannelSink {
+    sender: Mutex<Sender<SinkRecord>>,
+}
+
+impl ChannelSink {
+    pub fn new(sender: Sender<SinkRecord>) -> Self {
+        ChannelSink {
+            sender: Mutex::new(sender),
+        }
+    }
+}
+
+impl TraceSink for ChannelSink {
+    fn write(
+        &self,
+        trace: &DecisionTrace,
+        liability: Option<&LiabilityRecord>,
+    ) -> Result<(), SinkError> {
+        self.sender
+            .lock()
+            .map_err(|_| SinkError::LockError)?
+            .send(SinkRecord {
+                trace: trace.clone(),
+                liability: liability.cloned(),
+            })
+            .map_err(|_| SinkError::Closed)
+    }
+}
+
+type SinkCallback =
+    dyn Fn(&DecisionTrace, Option<&LiabilityRecord>) -> Result<(), SinkError> + Send + Sync;
+
+/// Hands each record to a caller-supplied function.
+pub struct CallbackSink {
+    callback: Box<SinkCallback>,
+}
+
+impl CallbackSink {
+    pub fn new(
+        callback: impl Fn(&DecisionTrace, Option<&LiabilityRecord>) -> Result<(), SinkError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        CallbackSink {
+            callback: Box::new(callback),
+        }
+    }
+}
+
+impl TraceSink for CallbackSink {
+    fn write(
+        &self,
+        trace: &DecisionTrace,
+        liability: Option<&LiabilityRecord>,
+    ) -> Result<(), SinkError> {
+        (self.callback)(trace, liability)
+    }
+}
@@ -0,0 +1,405 @@
+use crate::core::budget::{Budget, Usage};
+use crate::core::price::Price;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone)]
+pub enum StoreError {
+    #[error("consumption store I/O error: {0}")]
+    Io(String),
+    #[error("consumption log is corrupt at line {line}: {reason}")]
+    Corrupt { line: usize, reason: String },
+    #[error("usage of '{0}' would overflow or mix units")]
+    InvalidAmount(String),
+    #[error("internal lock error")]
+    LockError,
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(err: std::io::Error) -> Self {
+        StoreError::Io(err.to_string())
+    }
+}
+
+/// A debit held for a reservation, kept by the store so that a restarted
+/// gate can still release it once it expires.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReservationHold {
+    pub reservation_id: String,
+    pub authority_id: String,
+    /// `AuthorityUnit::hash` of the unit, for content-derived trace IDs.
+    pub authority_hash: String,
+    pub action_name: String,
+    pub action_scope: String,
+    pub amount: Price,
+    pub expires_at: f64,
+}
+
+/// Records how much of each authority unit has been spent. `debit` is the
+/// check-and-set at the heart of exhaustion: it must be atomic, and a
+/// successful debit must already be durable when it returns.
+pub trait ConsumptionStore: Send + Sync {
+    fn usage(&self, au_id: &str) -> Result<Usage, StoreError>;
+
+    /// Records one execution charged `amount` if `budget` still admits it.
+    /// Returns the usage after the debit, or `None` if the unit is exhausted.
+    fn debit(
+        &self,
+        au_id: &str,
+        amount: &Price,
+        budget: &Budget,
+    ) -> Result<Option<Usage>, StoreError>;
+
+    /// Reverses one debit of `amount` whose action did not take effect.
+    fn credit(&self, au_id: &str, amount: &Price) -> Result<(), StoreError>;
+
+    /// Returns `amount` of a debit whose execution was charged less than
+    /// was reserved. The execution itself still counts.
+    fn refund(&self, au_id: &str, amount: &Price) -> Result<(), StoreError>;
+
+    fn is_consumed(&self, au_id: &str) -> Result<bool, StoreError> {
+        Ok(self.usage(au_id)?.uses > 0)
+    }
+
+    /// Records that a debit already made is held for a reservation. Stores
+    /// that do not survive a restart need not keep holds.
+    fn hold(&self, _hold: &ReservationHold) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    /// Ends a hold before its reservation is settled or released. Returns
+    /// whether the hold was still in place.
+    fn release_hold(&self, _reservation_id: &str) -> Result<bool, StoreError> {
+        Ok(false)
+    }
+
+    /// Holds not yet released, including those from before a restart.
+    fn holds(&self) -> Result<Vec<ReservationHold>, StoreError> {
+        Ok(Vec::new())
+    }
+}
+
+/// Process-local store. Consumption does not survive a restart.
+#[derive(Debug, Default)]
+pub struct MemoryConsumptionStore {
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+impl MemoryConsumptionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ConsumptionStore for MemoryConsumptionStore {
+    fn usage(&self, au_id: &str) -> Result<Usage, StoreError> {
+        let guard = self.usage.lock().map_err(|_| StoreError::LockError)?;
+        Ok(guard.get(au_id).cloned().unwrap_or_default())
+    }
+
+    fn debit(
+        &self,
+        au_id: &str,
+        amount: &Price,
+        budget: &Budget,
+    ) -> Result<Option<Usage>, StoreError> {
+        let mut guard = self.usage.lock().map_err(|_| StoreError::LockError)?;
+        let usage = guard.entry(au_id.to_string()).or_default();
+        let Some(debited) = budget.debit(usage, amount) else {
+            return Ok(None);
+        };
+        *usage = debited.clone();
+        Ok(Some(debited))
+    }
+
+    fn credit(&self, au_id: &str, amount: &Price) -> Result<(), StoreError> {
+        let mut guard = self.usage.lock().map_err(|_| StoreError::LockError)?;
+        if let Some(usage) = guard.get_mut(au_id) {
+            *usage = usage
+                .credited(amount)
+                .ok_or_else(|| StoreError::InvalidAmount(au_id.to_string()))?;
+            if usage.uses == 0 {
+                guard.remove(au_id);
+            }
+        }
+        Ok(())
+    }
+
+    fn refund(&self, au_id: &str, amount: &Price) -> Result<(), StoreError> {
+        let mut guard = self.usage.lock().map_err(|_| StoreError::LockError)?;
+        if let Some(usage) = guard.get_mut(au_id) {
+            *usage = usage
+                .refunded(amount)
+                .ok_or_else(|| StoreError::InvalidAmount(au_id.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+struct FileState {
+    file: File,
+    /// Length of the log up to its last complete line.
+    len: u64,
+    usage: HashMap<String, Usage>,
+    holds: HashMap<String, ReservationHold>,
+}
+
+/// Append-only, fsynced consumption log. Each line is `debit`, `credit` or
+/// `refund`, then the hex-encoded ID, the amount and, unless the amount is
+/// unitless, its hex-encoded unit; usage is rebuilt by replaying the log on
+/// open. Logs written before budgets existed hold `consume <id>` lines, read
+/// as a debit of zero. A reservation hold is a `hold` line carrying every
+/// field of `ReservationHold`, ended by a `release` line naming the
+/// reservation. A torn final line left by a crash was never acknowledged and
+/// is discarded; one left by a failed write is truncated straight away.
+pub struct FileConsumptionStore {
+    path: PathBuf,
+    state: Mutex<FileState>,
+}
+
+impl FileConsumptionStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let created = !path.exists();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        if created {
+            sync_parent_dir(&path)?;
+        }
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        let complete = match contents.rfind('\n') {
+            Some(end) => end + 1,
+            None => 0,
+        };
+        if complete < contents.len() {
+            file.set_len(complete as u64)?;
+            file.sync_all()?;
+        }
+
+        let mut usage: HashMap<String, Usage> = HashMap::new();
+        let mut holds: HashMap<String, ReservationHold> = HashMap::new();
+        for (index, line) in contents[..complete].lines().enumerate() {
+            let corrupt = |reason: &str| StoreError::Corrupt {
+                line: index + 1,
+                reason: reason.to_string(),
+            };
+            let decode = |field: &str, reason: &str| {
+                hex::decode(field)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .ok_or_else(|| corrupt(reason))
+            };
+            let fields: Vec<&str> = line.split(' ').collect();
+            match fields[..] {
+                ["hold", reservation_id, authority_id, authority_hash, action_name, action_scope, amount, unit, expires_at] =>
+                {
+                    let hold = ReservationHold {
+                        reservation_id: decode(reservation_id, "malformed reservation ID")?,
+                        authority_id: decode(authority_id, "malformed ID")?,
+                        authority_hash: decode(authority_hash, "malformed hash")?,
+                        action_name: decode(action_name, "malformed action")?,
+                        action_scope: decode(action_scope, "malformed scope")?,
+                        amount: Price::new(
+                            amount.parse().map_err(|_| corrupt("malformed amount"))?,
+                            &decode(unit, "malformed unit")?,
+                        ),
+                        expires_at: expires_at
+                            .parse()
+                            .map_err(|_| corrupt("malformed deadline"))?,
+                    };
+                    holds.insert(hold.reservation_id.clone(), hold);
+                    continue;
+                }
+                ["release", reservation_id] => {
+                    holds.remove(&decode(reservation_id, "malformed reservation ID")?);
+                    continue;
+                }
+                _ => {}
+            }
+            let (op, encoded, amount, unit) = match fields[..] {
+                ["consume", encoded] => ("debit", encoded, "0", ""),
+                [op, encoded, amount] => (op, encoded, amount, ""),
+                [op, encoded, amount, unit] => (op, encoded, amount, unit),
+                _ => return Err(corrupt("expected three or four fields")),
+            };
+            let au_id = decode(encoded, "malformed ID")?;
+            let amount = Price::new(
+                amount.parse().map_err(|_| corrupt("malformed amount"))?,
+                &decode(unit, "malformed unit")?,
+            );
+            let entry = usage.entry(au_id).or_default();
+            *entry = match op {
+                "debit" => entry.debited(&amount),
+                "credit" => entry.credited(&amount),
+                "refund" => entry.refunded(&amount),
+                _ => return Err(corrupt("unknown operation")),
+            }
+            .ok_or_else(|| corrupt("usage overflows"))?;
+        }
+
+        Ok(FileConsumptionStore {
+            path,
+            state: Mutex::new(FileState {
+                file,
+                len: complete as u64,
+                usage,
+                holds,
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends one debit, credit or refund of `amount` to `au_id`.
+    fn append(
+        state: &mut FileState,
+        op: &str,
+        au_id: &str,
+        amount: &Price,
+    ) -> Result<(), StoreError> {
+        let mut line = format!("{} {} {}", op, hex::encode(au_id), amount.amount);
+        if !amount.unit.is_empty() {
+            line.push(' ');
+            line.push_str(&hex::encode(&amount.unit));
+        }
+        Self::append_line(state, line)
+    }
+
+    /// Appends and fsyncs one line. If either fails, the log is cut back to
+    /// its last complete line so later appends do not follow a torn one; a
+    /// cut that itself failed is retried before the next append.
+    fn append_line(state: &mut FileState, mut line: String) -> Result<(), StoreError> {
+        if state.file.metadata()?.len() != state.len {
+            state.file.set_len(state.len)?;
+            state.file.sync_data()?;
+        }
+        line.push('\n');
+        let written = state
+            .file
+            .write_all(line.as_bytes())
+            .and_then(|()| state.file.sync_data());
+        if let Err(err) = written {
+            let _ = state
+                .file
+                .set_len(state.len)
+                .and_then(|()| state.file.sync_data());
+            return Err(err.into());
+        }
+        state.len += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Makes a newly created log's directory entry durable.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<(), StoreError> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result<(), StoreError> {
+    Ok(())
+}
+
+impl ConsumptionStore for FileConsumptionStore {
+    fn usage(&self, au_id: &str) -> Result<Usage, StoreError> {
+        let guard = self.state.lock().map_err(|_| StoreError::LockError)?;
+        Ok(guard.usage.get(au_id).cloned().unwrap_or_default())
+    }
+
+    fn debit(
+        &self,
+        au_id: &str,
+        amount: &Price,
+        budget: &Budget,
+    ) -> Result<Option<Usage>, StoreError> {
+        let mut guard = self.state.lock().map_err(|_| StoreError::LockError)?;
+        let current = guard.usage.get(au_id).cloned().unwrap_or_default();
+        let Some(debited) = budget.debit(&current, amount) else {
+            return Ok(None);
+        };
+        Self::append(&mut guard, "debit", au_id, amount)?;
+        guard.usage.insert(au_id.to_string(), debited.clone());
+        Ok(Some(debited))
+    }
+
+    fn credit(&self, au_id: &str, amount: &Price) -> Result<(), StoreError> {
+        let mut guard = self.state.lock().map_err(|_| StoreError::LockError)?;
+        let Some(current) = guard.usage.get(au_id).cloned() else {
+            return Ok(());
+        };
+        let credited = current
+            .credited(amount)
+            .ok_or_else(|| StoreError::InvalidAmount(au_id.to_string()))?;
+        Self::append(&mut guard, "credit", au_id, amount)?;
+        guard.usage.insert(au_id.to_string(), credited);
+        Ok(())
+    }
+
+    fn refund(&self, au_id: &str, amount: &Price) -> Result<(), StoreError> {
+        let mut guard = self.state.lock().map_err(|_| StoreError::LockError)?;
+        let Some(current) = guard.usage.get(au_id).cloned() else {
+            return Ok(());
+        };
+        let refunded = current
+            .refunded(amount)
+            .ok_or_else(|| StoreError::InvalidAmount(au_id.to_string()))?;
+        Self::append(&mut guard, "refund", au_id, amount)?;
+        guard.usage.insert(au_id.to_string(), refunded);
+        Ok(())
+    }
+
+    fn hold(&self, hold: &ReservationHold) -> Result<(), StoreError> {
+        let mut guard = self.state.lock().map_err(|_| StoreError::LockError)?;
+        let line = format!(
+            "hold {} {} {} {} {} {} {} {}",
+            hex::encode(&hold.reservation_id),
+            hex::encode(&hold.authority_id),
+            hex::encode(&hold.authority_hash),
+            hex::encode(&hold.action_name),
+            hex::encode(&hold.action_scope),
+            hold.amount.amount,
+            hex::encode(&hold.amount.unit),
+            hold.expires_at
+        );
+        Self::append_line(&mut guard, line)?;
+        guard
+            .holds
+            .insert(hold.reservation_id.clone(), hold.clone());
+        Ok(())
+    }
+
+    fn release_hold(&self, reservation_id: &str) -> Result<bool, StoreError> {
+        let mut guard = self.state.lock().map_err(|_| StoreError::LockError)?;
+        if !guard.holds.contains_key(reservation_id) {
+            return Ok(false);
+        }
+        Self::append_line(
+            &mut guard,
+            format!("release {}", hex::encode(reservation_id)),
+        )?;
+        guard.holds.remove(reservation_id);
+        Ok(true)
+    }
+
+    fn holds(&self) -> Result<Vec<ReservationHold>, StoreError> {
+        let guard = self.state.lock().map_err(|_| StoreError::LockError)?;
+        Ok(guard.holds.values().cloned().collect())
+    }
+}
@@ -1,48 +1,434 @@
-use crate::core::authority::current_timestamp;
+use crate::core::apportionment::LiabilityLine;
+use crate::core::budget::Budget;
+use crate::core::canonical::{Canonical, CanonicalValue};
+use crate::core::clock::{Clock, SystemClock};
+use crate::core::policy::RuleEvaluation;
+use crate::core::price::Price;
+use sha2::{Digest, Sha256};
+use std::any::Any;
+use thiserror::Error;
 use uuid::Uuid;
 
-#[derive(Debug, Clone)]
+/// How an action's output is recorded in `DecisionTrace::result`. Outputs
+/// that are large or sensitive should summarize or digest themselves rather
+/// than be copied into the trace verbatim.
+pub trait TraceSummary {
+    fn trace_summary(&self) -> String;
+
+    /// The output itself, for a `Pricer` to downcast. Types priced from
+    /// their value rather than their summary return `Some(self)`.
+    fn priced_output(&self) -> Option<&dyn Any> {
+        None
+    }
+}
+
+impl TraceSummary for String {
+    fn trace_summary(&self) -> String {
+        self.clone()
+    }
+
+    fn priced_output(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+impl TraceSummary for str {
+    fn trace_summary(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<T: TraceSummary + ?Sized> TraceSummary for &T {
+    fn trace_summary(&self) -> String {
+        (**self).trace_summary()
+    }
+
+    fn priced_output(&self) -> Option<&dyn Any> {
+        (**self).priced_output()
+    }
+}
+
+impl TraceSummary for () {
+    fn trace_summary(&self) -> String {
+        String::new()
+    }
+}
+
+/// Binary outputs are recorded as their SHA-256 digest.
+impl TraceSummary for Vec<u8> {
+    fn trace_summary(&self) -> String {
+        format!("sha256:{:x}", Sha256::digest(self))
+    }
+
+    fn priced_output(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+macro_rules! display_trace_summary {
+    ($($ty:ty),*) => {
+        $(impl TraceSummary for $ty {
+            fn trace_summary(&self) -> String {
+                self.to_string()
+            }
+
+            fn priced_output(&self) -> Option<&dyn Any> {
+                Some(self)
+            }
+        })*
+    };
+}
+
+display_trace_summary!(bool, i32, i64, u32, u64, usize);
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum TraceLogError {
+    #[error("trace at position {index} has sequence {found}, expected {expected}")]
+    SequenceMismatch {
+        index: usize,
+        expected: u64,
+        found: u64,
+    },
+    #[error("trace at position {0} does not link to its predecessor")]
+    BrokenLink(usize),
+    #[error("log head {actual} does not match expected head {expected}")]
+    HeadMismatch { expected: String, actual: String },
+}
+
+/// What a trace records: a debited request about to run its action, an
+/// executed action, a request the gate refused, an action that ran and
+/// failed, the compensation that undid it, or a revocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TraceOutcome {
+    Admitted,
+    #[default]
+    Executed,
+    Denied,
+    Failed,
+    Compensated,
+    Revoked,
+}
+
+impl TraceOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TraceOutcome::Admitted => "admitted",
+            TraceOutcome::Executed => "executed",
+            TraceOutcome::Denied => "denied",
+            TraceOutcome::Failed => "failed",
+            TraceOutcome::Compensated => "compensated",
+            TraceOutcome::Revoked => "revoked",
+        }
+    }
+}
+
+/// How trace and liability record IDs are assigned. Content-derived IDs are
+/// a digest of what was executed and where it sits in the chain, so replays
+/// and independent nodes produce the same IDs for the same execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdStrategy {
+    #[default]
+    Random,
+    ContentDerived,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DecisionTrace {
     pub action_name: String,
     pub authority_id: String,
     pub timestamp: f64,
     pub result: String,
     pub id: String,
+    pub sequence: u64,
+    pub prev_hash: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub outcome: TraceOutcome,
+    /// Machine-readable error kind for denied and failed traces.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub error_kind: Option<String>,
+    /// How the gate's policy chain judged the attempt, one entry per rule.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub policy: Vec<RuleEvaluation>,
 }
 
 impl DecisionTrace {
     pub fn new(action_name: String, authority_id: String, result: String) -> Self {
-        let timestamp = current_timestamp();
+        Self::with_clock(action_name, authority_id, result, &SystemClock)
+    }
+
+    pub fn with_clock(
+        action_name: String,
+        authority_id: String,
+        result: String,
+        clock: &dyn Clock,
+    ) -> Self {
+        let timestamp = clock.now();
         DecisionTrace {
             action_name,
             authority_id,
             timestamp,
             result,
             id: Uuid::new_v4().to_string(),
+            sequence: 0,
+            prev_hash: None,
+            outcome: TraceOutcome::Executed,
+            error_kind: None,
+            policy: Vec::new(),
+        }
+    }
+
+    /// A trace for an attempt that did not execute successfully; `result`
+    /// carries the error message.
+    pub fn rejected(
+        outcome: TraceOutcome,
+        action_name: String,
+        authority_id: String,
+        error_kind: &str,
+        message: String,
+        clock: &dyn Clock,
+    ) -> Self {
+        DecisionTrace {
+            outcome,
+            error_kind: Some(error_kind.to_string()),
+            ..DecisionTrace::with_clock(action_name, authority_id, message, clock)
         }
     }
+
+    /// SHA-256 of the trace's canonical JSON.
+    pub fn hash(&self) -> String {
+        self.canonical_value().sha256_hex()
+    }
+
+    /// Content-derived trace ID: the SHA-256 of the authority unit's hash,
+    /// the action name and scope, the digest of the recorded result and the
+    /// trace's sequence number.
+    pub fn derive_id(
+        authority_hash: &str,
+        action_name: &str,
+        action_scope: &str,
+        result: &str,
+        sequence: u64,
+    ) -> String {
+        let result_digest = format!("{:x}", Sha256::digest(result.as_bytes()));
+        CanonicalValue::object([
+            ("authority_hash", CanonicalValue::str(authority_hash)),
+            ("action_name", CanonicalValue::str(action_name)),
+            ("scope", CanonicalValue::str(action_scope)),
+            ("result_digest", CanonicalValue::Str(result_digest)),
+            ("sequence", CanonicalValue::UInt(sequence)),
+        ])
+        .sha256_hex()
+    }
+}
+
+impl Canonical for DecisionTrace {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("id", CanonicalValue::str(&self.id)),
+            ("sequence", CanonicalValue::UInt(self.sequence)),
+            ("action_name", CanonicalValue::str(&self.action_name)),
+            ("authority_id", CanonicalValue::str(&self.authority_id)),
+            ("timestamp", CanonicalValue::Float(self.timestamp)),
+            ("result", CanonicalValue::str(&self.result)),
+            (
+                "prev_hash",
+                CanonicalValue::opt_str(self.prev_hash.as_deref()),
+            ),
+            ("outcome", CanonicalValue::str(self.outcome.as_str())),
+            (
+                "error_kind",
+                CanonicalValue::opt_str(self.error_kind.as_deref()),
+            ),
+            (
+                "policy",
+                CanonicalValue::Array(self.policy.iter().map(Canonical::canonical_value).collect()),
+            ),
+        ])
+    }
+}
+
+/// Append-only sequence of traces in which each entry embeds the hash of its
+/// predecessor. The integrity check is `verify_head`, against a head hash
+/// recorded elsewhere: it detects any modified, reordered, removed or
+/// truncated entry. `verify_links` only checks the entries against each
+/// other, so it cannot see a change to the last entry or a truncated tail.
+#[derive(Debug, Clone, Default)]
+pub struct TraceLog {
+    entries: Vec<DecisionTrace>,
 }
 
-#[derive(Debug, Clone)]
+impl TraceLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps previously stored entries as-is. Call `verify_head` before
+    /// trusting them.
+    pub fn from_entries(entries: Vec<DecisionTrace>) -> Self {
+        TraceLog { entries }
+    }
+
+    /// Links `trace` to the current head and appends it.
+    pub fn append(&mut self, mut trace: DecisionTrace) -> &DecisionTrace {
+        trace.sequence = self.entries.len() as u64;
+        trace.prev_hash = self.head();
+        self.entries.push(trace);
+        &self.entries[self.entries.len() - 1]
+    }
+
+    pub fn entries(&self) -> &[DecisionTrace] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn head(&self) -> Option<String> {
+        self.entries.last().map(DecisionTrace::hash)
+    }
+
+    /// Checks sequence numbers and that each entry links to its
+    /// predecessor. Not sufficient on its own; see `verify_head`.
+    pub fn verify_links(&self) -> Result<(), TraceLogError> {
+        let mut prev_hash: Option<String> = None;
+        for (index, trace) in self.entries.iter().enumerate() {
+            if trace.sequence != index as u64 {
+                return Err(TraceLogError::SequenceMismatch {
+                    index,
+                    expected: index as u64,
+                    found: trace.sequence,
+                });
+            }
+            if trace.prev_hash != prev_hash {
+                return Err(TraceLogError::BrokenLink(index));
+            }
+            prev_hash = Some(trace.hash());
+        }
+        Ok(())
+    }
+
+    /// Checks the links and that the log ends at the anchored `expected`
+    /// head, which pins every entry's content.
+    pub fn verify_head(&self, expected: &str) -> Result<(), TraceLogError> {
+        self.verify_links()?;
+        let actual = self.head().unwrap_or_default();
+        if actual != expected {
+            return Err(TraceLogError::HeadMismatch {
+                expected: expected.to_string(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LiabilityRecord {
     pub trace_id: String,
     pub authority_id: String,
-    pub price: i64,
+    /// The amount charged for the execution.
+    pub price: Price,
     pub scope: String,
     pub timestamp: f64,
     pub id: String,
+    /// Budget left on a multi-use unit after this execution; `None` for
+    /// single-use units.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub remaining_budget: Option<Budget>,
+    /// Each accountable party's share of `price`, summing exactly to it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub line_items: Vec<LiabilityLine>,
+    /// The unit's price when the charge was computed by a pricer after the
+    /// action ran; `price` never exceeds it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub quoted_price: Option<Price>,
 }
 
 impl LiabilityRecord {
-    pub fn new(trace_id: String, authority_id: String, price: i64, scope: String) -> Self {
-        let timestamp = current_timestamp();
+    pub fn new(
+        trace_id: String,
+        authority_id: String,
+        price: impl Into<Price>,
+        scope: String,
+    ) -> Self {
+        Self::with_clock(trace_id, authority_id, price, scope, &SystemClock)
+    }
+
+    pub fn with_clock(
+        trace_id: String,
+        authority_id: String,
+        price: impl Into<Price>,
+        scope: String,
+        clock: &dyn Clock,
+    ) -> Self {
+        let timestamp = clock.now();
         LiabilityRecord {
             trace_id,
             authority_id,
-            price,
+            price: price.into(),
             scope,
             timestamp,
             id: Uuid::new_v4().to_string(),
+            remaining_budget: None,
+            line_items: Vec::new(),
+            quoted_price: None,
         }
     }
 }
+
+impl LiabilityRecord {
+    /// SHA-256 of the record's canonical JSON.
+    pub fn hash(&self) -> String {
+        self.canonical_value().sha256_hex()
+    }
+
+    /// Content-derived liability ID for the record accompanying `trace_id`.
+    pub fn derive_id(trace_id: &str) -> String {
+        CanonicalValue::object([("liability_for", CanonicalValue::str(trace_id))]).sha256_hex()
+    }
+}
+
+impl Canonical for LiabilityRecord {
+    fn canonical_value(&self) -> CanonicalValue {
+        CanonicalValue::object([
+            ("id", CanonicalValue::str(&self.id)),
+            ("trace_id", CanonicalValue::str(&self.trace_id)),
+            ("authority_id", CanonicalValue::str(&self.authority_id)),
+            ("price", self.price.canonical_value()),
+            ("scope", CanonicalValue::str(&self.scope)),
+            ("timestamp", CanonicalValue::Float(self.timestamp)),
+            (
+                "remaining_budget",
+                self.remaining_budget
+                    .as_ref()
+                    .map(Canonical::canonical_value)
+                    .unwrap_or(CanonicalValue::Null),
+            ),
+            (
+                "quoted_price",
+                self.quoted_price
+                    .as_ref()
+                    .map(Canonical::canonical_value)
+                    .unwrap_or(CanonicalValue::Null),
+            ),
+            (
+                "line_items",
+                CanonicalValue::Array(
+                    self.line_items
+                        .iter()
+                        .map(Canonical::canonical_value)
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+}
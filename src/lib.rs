@@ -5,14 +5,45 @@
 
 pub mod core;
 
+pub use core::apportionment::{Apportionment, ApportionmentError, LiabilityLine};
 pub use core::authority::{current_timestamp, AuthorityError, AuthorityUnit};
-pub use core::gate::{ExecutionGate, ExecutionGateError};
+pub use core::budget::{Budget, Usage};
+pub use core::canonical::{Canonical, CanonicalValue};
+pub use core::clock::{Clock, FixedClock, ManualClock, SystemClock};
+pub use core::delegation::DelegationRecord;
+pub use core::gate::{
+    ActionError, Compensation, ExecutionGate, ExecutionGateError, Reservation,
+    ReservationCompensation,
+};
+pub use core::issuance::IssuanceRule;
+pub use core::ledger::{Account, EntrySide, LedgerError, LiabilityLedger, Posting, Statement};
 pub use core::manager::{AuthorityManager, ManagerError};
-pub use core::trace::{DecisionTrace, LiabilityRecord};
+pub use core::policy::{ExecutionRule, PolicyChain, PolicyContext, PolicyError, RuleEvaluation};
+pub use core::price::{Price, PriceError};
+pub use core::pricing::{bounded_charge, Pricer, PricingContext};
+pub use core::ratelimit::{RateLimit, RateLimitError, RateLimitKey, RateLimiter};
+pub use core::replay::{
+    Divergence, ExecutionRequest, ReplayError, ReplayEvent, ReplayReport, Replayer,
+};
+pub use core::revocation::RevocationList;
+pub use core::scope::{Scope, ScopeError};
+pub use core::signing::{IssuerSignature, KeyRegistry, SignatureError};
+pub use core::sink::{
+    CallbackSink, ChainPosition, ChannelSink, JsonlFileSink, MemoryTraceSink, SinkError,
+    SinkRecord, TraceSink,
+};
+pub use core::store::{
+    ConsumptionStore, FileConsumptionStore, MemoryConsumptionStore, ReservationHold, StoreError,
+};
+pub use core::trace::{
+    DecisionTrace, IdStrategy, LiabilityRecord, TraceLog, TraceLogError, TraceOutcome, TraceSummary,
+};
 
 #[cfg(test)]
 mod tests {
     use crate::*;
+    use std::io::Write;
+    use std::sync::Arc;
 
     #[test]
     fn test_authority_unit_creation() {
@@ -28,7 +59,7 @@ mod tests {
 
         assert_eq!(au.id, "test-123");
         assert_eq!(au.scope, "read");
-        assert_eq!(au.price, 10);
+        assert_eq!(au.price, Price::from(10));
         assert_eq!(au.delegation_chain.len(), 2);
     }
 
@@ -153,7 +184,7 @@ mod tests {
         assert_eq!(trace.action_name, "test_action");
         assert_eq!(trace.authority_id, "test-123");
         assert_eq!(liability.authority_id, "test-123");
-        assert_eq!(liability.price, 10);
+        assert_eq!(liability.price, Price::from(10));
     }
 
     #[test]
@@ -477,9 +508,14 @@ mod tests {
             id: "".to_string(),
             scope: "read".to_string(),
             delegation_chain: vec!["root".to_string()],
-            price: 10,
+            price: Price::from(10),
             timestamp: 1640995200.0,
             prev_hash: None,
+            signature: None,
+            delegations: Vec::new(),
+            budget: None,
+            not_before: None,
+            expires_at: None,
         };
 
         assert!(matches!(
@@ -487,4 +523,2673 @@ mod tests {
             Err(ManagerError::InvalidAuthority(_))
         ));
     }
+
+    fn signing_key(seed: u8) -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(&[seed; 32])
+    }
+
+    #[test]
+    fn test_signed_authority_validates_without_local_issuance() {
+        let issuer_key = signing_key(7);
+        let manager = AuthorityManager::with_max_age(i64::MAX);
+        manager
+            .trust_issuer("issuing-service", issuer_key.verifying_key())
+            .unwrap();
+
+        let mut au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            current_timestamp(),
+            None,
+        )
+        .unwrap();
+        assert!(!manager.validate_authority(&au));
+
+        au.sign("issuing-service", &issuer_key);
+        assert_eq!(au.issuer(), "issuing-service");
+        assert!(manager.validate_authority(&au));
+
+        let mut forged = au.clone();
+        forged.price = Price::from(1);
+        assert!(!manager.validate_authority(&forged));
+    }
+
+    #[test]
+    fn test_signed_authority_rejects_untrusted_issuer() {
+        let manager = AuthorityManager::with_max_age(i64::MAX);
+        manager
+            .trust_issuer("issuing-service", signing_key(7).verifying_key())
+            .unwrap();
+
+        let mut au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            current_timestamp(),
+            None,
+        )
+        .unwrap();
+        au.sign("issuing-service", &signing_key(8));
+
+        assert!(!manager.validate_authority(&au));
+        assert!(matches!(
+            manager.issue_authority(au.clone()),
+            Err(ManagerError::InvalidSignature(_))
+        ));
+
+        manager.distrust_issuer("issuing-service").unwrap();
+        assert!(matches!(
+            au.verify_signature(&KeyRegistry::new()),
+            Err(SignatureError::UntrustedPrincipal(_))
+        ));
+    }
+
+    fn delegated_authority(hop_scope: &str, hop_price: i64) -> AuthorityUnit {
+        let root = DelegationRecord::signed(
+            "test-123",
+            None,
+            "root",
+            "agent",
+            "any",
+            100,
+            &signing_key(1),
+        );
+        let hop = DelegationRecord::signed(
+            "test-123",
+            Some(&root),
+            "agent",
+            "sub-agent",
+            hop_scope,
+            hop_price,
+            &signing_key(2),
+        );
+        let mut au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec![
+                "root".to_string(),
+                "agent".to_string(),
+                "sub-agent".to_string(),
+            ],
+            10,
+            current_timestamp(),
+            None,
+        )
+        .unwrap();
+        au.delegations = vec![root, hop];
+        au
+    }
+
+    /// Signs every hop of `au`'s chain at the unit's own scope and price,
+    /// registering each delegator with `manager` and granting the root the
+    /// same authority.
+    fn delegate_through(manager: &AuthorityManager, au: AuthorityUnit) -> AuthorityUnit {
+        let mut records: Vec<DelegationRecord> = Vec::new();
+        for (index, hop) in au.delegation_chain.windows(2).enumerate() {
+            let key = signing_key(100 + index as u8);
+            manager
+                .register_delegator(&hop[0], key.verifying_key())
+                .unwrap();
+            let record = DelegationRecord::signed(
+                &au.id,
+                records.last(),
+                &hop[0],
+                &hop[1],
+                &au.scope,
+                au.price.clone(),
+                &key,
+            );
+            records.push(record);
+        }
+        manager
+            .grant_root_authority(au.issuer(), &au.scope, au.price.clone())
+            .unwrap();
+        au.with_delegations(records).unwrap()
+    }
+
+    #[test]
+    fn test_delegation_chain_verifies_each_hop() {
+        let manager = AuthorityManager::with_max_age(i64::MAX);
+        manager
+            .register_delegator("root", signing_key(1).verifying_key())
+            .unwrap();
+        manager
+            .register_delegator("agent", signing_key(2).verifying_key())
+            .unwrap();
+
+        let au = delegated_authority("read", 50);
+        assert!(au.validate_invariants().is_ok());
+        assert!(matches!(
+            manager.issue_authority(au.clone()),
+            Err(ManagerError::InvalidDelegation(_))
+        ));
+        manager.grant_root_authority("root", "any", 99).unwrap();
+        assert!(matches!(
+            manager.issue_authority(au.clone()),
+            Err(ManagerError::InvalidDelegation(_))
+        ));
+        manager.grant_root_authority("root", "any", 100).unwrap();
+        manager.issue_authority(au.clone()).unwrap();
+        assert!(manager.validate_authority(&au));
+
+        // A chain without hop records proves nothing.
+        let mut unproven = au.clone();
+        unproven.delegations.clear();
+        assert!(!manager.validate_authority(&unproven));
+
+        let mut tampered = delegated_authority("read", 50);
+        tampered.id = "test-456".to_string();
+        assert!(matches!(
+            manager.issue_authority(tampered),
+            Err(ManagerError::InvalidDelegation(_))
+        ));
+    }
+
+    #[test]
+    fn test_delegation_chain_rejects_widening_hops() {
+        assert!(matches!(
+            delegated_authority("read", 500).validate_invariants(),
+            Err(AuthorityError::DelegationWidensPrice(1))
+        ));
+        assert!(matches!(
+            delegated_authority("write", 50).validate_invariants(),
+            Err(AuthorityError::DelegationWidensScope(2))
+        ));
+
+        let mut short = delegated_authority("read", 50);
+        short.delegations.pop();
+        assert!(matches!(
+            short.validate_invariants(),
+            Err(AuthorityError::DelegationLengthMismatch {
+                expected: 2,
+                actual: 1,
+                ..
+            })
+        ));
+
+        let mut swapped = delegated_authority("read", 50);
+        swapped.delegation_chain.swap(1, 2);
+        assert!(matches!(
+            swapped.validate_invariants(),
+            Err(AuthorityError::DelegationHopMismatch(0))
+        ));
+
+        let mut bare = delegated_authority("read", 50);
+        bare.delegations.clear();
+        assert!(matches!(
+            bare.validate_invariants(),
+            Err(AuthorityError::DelegationLengthMismatch { actual: 0, .. })
+        ));
+
+        let mut reissued = delegated_authority("read", 50);
+        reissued.sign("other-issuer", &signing_key(9));
+        assert!(matches!(
+            reissued.validate_invariants(),
+            Err(AuthorityError::DelegationIssuerMismatch(_))
+        ));
+    }
+
+    fn scope(s: &str) -> Scope {
+        Scope::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_scope_wildcards_and_prefixes() {
+        assert!(scope("db:orders:read").is_subset_of(&scope("db:orders:read")));
+        assert!(scope("db:orders:read").is_subset_of(&scope("db:*:read")));
+        assert!(!scope("db:orders:write").is_subset_of(&scope("db:*:read")));
+        assert!(scope("payments:refund").is_subset_of(&scope("payments:*")));
+        assert!(scope("payments:refund:create").is_subset_of(&scope("payments:*")));
+        assert!(!scope("payments").is_subset_of(&scope("payments:*")));
+        assert!(scope("fs:/data/a/b.csv:write").is_subset_of(&scope("fs:/data/**:write")));
+        assert!(!scope("fs:/etc/passwd:write").is_subset_of(&scope("fs:/data/**:write")));
+        assert!(!scope("fs:/data/a/b.csv:read").is_subset_of(&scope("fs:/data/*:read")));
+        assert!(scope("anything:at:all").is_subset_of(&scope("any")));
+        assert!(matches!(
+            Scope::parse("db::read"),
+            Err(ScopeError::EmptySegment(_))
+        ));
+    }
+
+    #[test]
+    fn test_scope_subset_between_patterns() {
+        assert!(scope("db:orders:*").is_subset_of(&scope("db:*")));
+        assert!(!scope("db:*").is_subset_of(&scope("db:orders:*")));
+        assert!(scope("fs:/data/x/*:write").is_subset_of(&scope("fs:/data/**:write")));
+        assert!(!scope("fs:/data/**:write").is_subset_of(&scope("fs:/data/*:write")));
+        assert!(!scope("payments:*").is_subset_of(&scope("payments:refund")));
+        assert!(scope("read:*").is_subset_of(&scope("any")));
+
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "db:orders:*".to_string(),
+            vec!["root".to_string()],
+            10,
+            1640995200.0,
+            None,
+        )
+        .unwrap();
+        assert!(au.can_consume("db:orders:read"));
+        assert!(!au.can_consume("db:users:read"));
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "able-{}-{}-{}",
+            name,
+            std::process::id(),
+            uuid::Uuid::new_v4()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_file_consumption_store_survives_restart() {
+        let path = temp_path("consumed.log");
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            1640995200.0,
+            None,
+        )
+        .unwrap();
+
+        {
+            let store = Arc::new(FileConsumptionStore::open(&path).unwrap());
+            let gate = ExecutionGate::new(|_| true).with_store(store);
+            gate.execute_with_authority(&au, &|| Ok("success".to_string()), "test_action", "read")
+                .unwrap();
+            let failed = gate.execute_with_authority(
+                &AuthorityUnit {
+                    id: "test-456".to_string(),
+                    ..au.clone()
+                },
+                &|| Err("Action failed".to_string()),
+                "failing_action",
+                "read",
+            );
+            assert!(failed.is_err());
+        }
+
+        let store = Arc::new(FileConsumptionStore::open(&path).unwrap());
+        assert!(store.is_consumed("test-123").unwrap());
+        assert!(!store.is_consumed("test-456").unwrap());
+        let gate = ExecutionGate::new(|_| true).with_store(store);
+        assert!(matches!(
+            gate.execute_with_authority(&au, &|| Ok("success".to_string()), "test_action", "read"),
+            Err(ExecutionGateError::AlreadyConsumed(_))
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        // Logs from before budgets recorded bare `consume` lines.
+        let legacy = temp_path("legacy.log");
+        std::fs::write(&legacy, format!("consume {}\n", hex::encode("test-123"))).unwrap();
+        let store = FileConsumptionStore::open(&legacy).unwrap();
+        assert_eq!(
+            store.usage("test-123").unwrap(),
+            Usage {
+                uses: 1,
+                spent: Price::from(0)
+            }
+        );
+        let _ = std::fs::remove_file(&legacy);
+    }
+
+    #[test]
+    fn test_file_consumption_store_discards_torn_tail() {
+        let path = temp_path("torn.log");
+        std::fs::write(
+            &path,
+            format!("debit {} 0\ndebit {} 0", hex::encode("a"), hex::encode("b")),
+        )
+        .unwrap();
+
+        let store = FileConsumptionStore::open(&path).unwrap();
+        assert!(store.is_consumed("a").unwrap());
+        assert!(!store.is_consumed("b").unwrap());
+        assert!(store
+            .debit("b", &Price::from(0), &Budget::Uses(1))
+            .unwrap()
+            .is_some());
+        drop(store);
+
+        let store = FileConsumptionStore::open(&path).unwrap();
+        assert!(store.is_consumed("b").unwrap());
+
+        // A partial line from a failed write is cut before the next append.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"debit 6")
+            .unwrap();
+        assert!(store
+            .debit("c", &Price::from(0), &Budget::Uses(1))
+            .unwrap()
+            .is_some());
+        drop(store);
+        let store = FileConsumptionStore::open(&path).unwrap();
+        assert!(store.is_consumed("c").unwrap());
+        drop(store);
+
+        std::fs::write(&path, "bogus\n").unwrap();
+        assert!(matches!(
+            FileConsumptionStore::open(&path),
+            Err(StoreError::Corrupt { line: 1, .. })
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn sample_trace_log() -> TraceLog {
+        let mut log = TraceLog::new();
+        for action in ["first", "second", "third"] {
+            log.append(DecisionTrace::new(
+                action.to_string(),
+                "test-123".to_string(),
+                "success".to_string(),
+            ));
+        }
+        log
+    }
+
+    #[test]
+    fn test_trace_log_links_entries() {
+        let log = sample_trace_log();
+        assert!(log.verify_links().is_ok());
+        assert_eq!(log.entries()[0].prev_hash, None);
+        assert_eq!(log.entries()[2].prev_hash, Some(log.entries()[1].hash()));
+        let head = log.head().unwrap();
+        assert!(log.verify_head(&head).is_ok());
+
+        let mut truncated = log.entries().to_vec();
+        truncated.pop();
+        assert!(matches!(
+            TraceLog::from_entries(truncated).verify_head(&head),
+            Err(TraceLogError::HeadMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_trace_log_detects_tampering() {
+        let mut modified = sample_trace_log().entries().to_vec();
+        modified[1].result = "forged".to_string();
+        assert_eq!(
+            TraceLog::from_entries(modified).verify_links(),
+            Err(TraceLogError::BrokenLink(2))
+        );
+
+        let mut reordered = sample_trace_log().entries().to_vec();
+        reordered.swap(1, 2);
+        assert!(TraceLog::from_entries(reordered).verify_links().is_err());
+
+        // Only the anchored head pins the last entry.
+        let log = sample_trace_log();
+        let head = log.head().unwrap();
+        let mut last = log.entries().to_vec();
+        last[2].result = "forged".to_string();
+        let last = TraceLog::from_entries(last);
+        assert!(last.verify_links().is_ok());
+        assert!(matches!(
+            last.verify_head(&head),
+            Err(TraceLogError::HeadMismatch { .. })
+        ));
+
+        let mut deleted = sample_trace_log().entries().to_vec();
+        deleted.remove(0);
+        assert!(matches!(
+            TraceLog::from_entries(deleted).verify_links(),
+            Err(TraceLogError::SequenceMismatch { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_canonical_json_is_what_hash_covers() {
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string(), "user \"a\"".to_string()],
+            10,
+            1640995200.5,
+            None,
+        )
+        .unwrap();
+
+        let json = au.canonical_json();
+        assert_eq!(
+            json,
+            r#"{"budget":null,"delegation_chain":["root","user \"a\""],"delegations":[],"expires_at":null,"id":"test-123","not_before":null,"prev_hash":null,"price":{"amount":10,"unit":""},"scope":"read","timestamp":1640995200.5}"#
+        );
+        let digest = {
+            use sha2::Digest;
+            format!("{:x}", sha2::Sha256::digest(json.as_bytes()))
+        };
+        assert_eq!(au.hash(), digest);
+
+        let mut signed = au.clone();
+        signed.sign("issuing-service", &signing_key(7));
+        assert_eq!(signed.hash(), au.hash());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trips_core_types() {
+        let mut au = delegated_authority("read", 50);
+        au.sign("issuing-service", &signing_key(7));
+        let wire = serde_json::to_string(&au).unwrap();
+        let decoded: AuthorityUnit = serde_json::from_str(&wire).unwrap();
+        assert_eq!(decoded, au);
+        assert_eq!(decoded.hash(), au.hash());
+
+        let from_canonical: AuthorityUnit = serde_json::from_str(&au.canonical_json()).unwrap();
+        assert_eq!(from_canonical.hash(), au.hash());
+        assert_eq!(from_canonical.signature, None);
+
+        let gate = ExecutionGate::new(|_| true);
+        let (trace, liability) = gate
+            .execute_with_authority(&au, &|| Ok("success".to_string()), "test_action", "read")
+            .unwrap();
+        let trace_json = serde_json::to_string(&trace).unwrap();
+        assert_eq!(
+            serde_json::from_str::<DecisionTrace>(&trace_json).unwrap(),
+            trace
+        );
+        let decoded: LiabilityRecord = serde_json::from_str(&liability.canonical_json()).unwrap();
+        assert_eq!(decoded.hash(), liability.hash());
+
+        let unitless: Price = serde_json::from_str("10").unwrap();
+        assert_eq!(unitless, Price::from(10));
+    }
+
+    #[test]
+    fn test_revoked_authority_is_rejected_by_gate() {
+        let manager = Arc::new(AuthorityManager::with_max_age(i64::MAX));
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            current_timestamp(),
+            None,
+        )
+        .unwrap();
+        manager.issue_authority(au.clone()).unwrap();
+
+        let validator = Arc::clone(&manager);
+        let gate = ExecutionGate::new(move |au| validator.validate_authority(au));
+
+        let trace = manager.revoke_authority("test-123", "compromised").unwrap();
+        assert_eq!(trace.action_name, "revoke_authority");
+        assert_eq!(trace.authority_id, "test-123");
+        assert_eq!(trace.result, "compromised");
+        assert!(manager.is_revoked(&au));
+        assert!(matches!(
+            gate.execute_with_authority(&au, &|| Ok("success".to_string()), "test_action", "read"),
+            Err(ExecutionGateError::InvalidAuthority(_))
+        ));
+        assert!(matches!(
+            manager.revoke_authority("test-123", "again"),
+            Err(ManagerError::AlreadyRevoked(_))
+        ));
+    }
+
+    #[test]
+    fn test_revoked_principal_cascades_to_delegated_units() {
+        let manager = AuthorityManager::with_max_age(i64::MAX);
+        let ts = current_timestamp();
+        let delegated = delegate_through(
+            &manager,
+            AuthorityUnit::new(
+                "test-123".to_string(),
+                "read".to_string(),
+                vec!["root".to_string(), "agent".to_string()],
+                10,
+                ts,
+                None,
+            )
+            .unwrap(),
+        );
+        let unrelated = delegate_through(
+            &manager,
+            AuthorityUnit::new(
+                "test-456".to_string(),
+                "read".to_string(),
+                vec!["root".to_string(), "other".to_string()],
+                10,
+                ts,
+                None,
+            )
+            .unwrap(),
+        );
+        manager.issue_authority(delegated.clone()).unwrap();
+        manager.issue_authority(unrelated.clone()).unwrap();
+
+        manager
+            .revoke_principal("agent", "agent compromised")
+            .unwrap();
+        manager
+            .revoke_authority("test-789", "never issued")
+            .unwrap();
+        assert!(!manager.validate_authority(&delegated));
+        assert!(manager.validate_authority(&unrelated));
+
+        let log = manager.revocation_log().unwrap();
+        assert_eq!(log.len(), 2);
+        assert!(log.verify_links().is_ok());
+        assert_eq!(log.entries()[0].action_name, "revoke_principal");
+    }
+
+    #[test]
+    fn test_use_budget_allows_repeated_execution() {
+        let gate = ExecutionGate::new(|_| true);
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "api:call".to_string(),
+            vec!["root".to_string()],
+            1,
+            1640995200.0,
+            None,
+        )
+        .unwrap()
+        .with_budget(Budget::Uses(2))
+        .unwrap();
+
+        let (_, first) = gate
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "call", "api:call")
+            .unwrap();
+        assert_eq!(first.remaining_budget, Some(Budget::Uses(1)));
+
+        let failed =
+            gate.execute_with_authority(&au, &|| Err("boom".to_string()), "call", "api:call");
+        assert!(matches!(failed, Err(ExecutionGateError::ActionFailed(_))));
+        assert_eq!(gate.remaining(&au).unwrap(), Budget::Uses(1));
+
+        let (_, second) = gate
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "call", "api:call")
+            .unwrap();
+        assert_eq!(second.remaining_budget, Some(Budget::Uses(0)));
+        assert!(matches!(
+            gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "call", "api:call"),
+            Err(ExecutionGateError::BudgetExhausted(_))
+        ));
+
+        // Usage that would overflow is refused rather than panicking under
+        // the store lock.
+        let pricey = AuthorityUnit::new(
+            "test-456".to_string(),
+            "api:call".to_string(),
+            vec!["root".to_string()],
+            i64::MAX,
+            1640995200.0,
+            None,
+        )
+        .unwrap()
+        .with_budget(Budget::Uses(3))
+        .unwrap();
+        gate.execute_with_authority(&pricey, &|| Ok("ok".to_string()), "call", "api:call")
+            .unwrap();
+        assert!(matches!(
+            gate.execute_with_authority(&pricey, &|| Ok("ok".to_string()), "call", "api:call"),
+            Err(ExecutionGateError::BudgetExhausted(_))
+        ));
+        assert_eq!(gate.remaining(&pricey).unwrap(), Budget::Uses(2));
+
+        // Unitless spend only adopts a unit while it is still zero.
+        let usd = Usage::default().debited(&Price::new(3, "USD")).unwrap();
+        assert_eq!(usd.spent, Price::new(3, "USD"));
+        let unitless = Usage::default().debited(&Price::from(5)).unwrap();
+        assert_eq!(unitless.debited(&Price::new(3, "USD")), None);
+        assert_eq!(unitless.credited(&Price::new(5, "USD")), None);
+        assert_eq!(
+            Budget::Spend(Price::new(10, "USD")).remaining(&unitless),
+            Budget::Spend(Price::new(0, "USD"))
+        );
+    }
+
+    #[test]
+    fn test_spend_budget_survives_restart() {
+        let path = temp_path("spend.log");
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "payments:*".to_string(),
+            vec!["root".to_string()],
+            200,
+            1640995200.0,
+            None,
+        )
+        .unwrap()
+        .with_budget(Budget::Spend(Price::from(500)))
+        .unwrap();
+
+        {
+            let gate = ExecutionGate::new(|_| true)
+                .with_store(Arc::new(FileConsumptionStore::open(&path).unwrap()));
+            let (_, record) = gate
+                .execute_with_authority(&au, &|| Ok("ok".to_string()), "pay", "payments:send")
+                .unwrap();
+            assert_eq!(
+                record.remaining_budget,
+                Some(Budget::Spend(Price::from(300)))
+            );
+        }
+
+        let gate = ExecutionGate::new(|_| true)
+            .with_store(Arc::new(FileConsumptionStore::open(&path).unwrap()));
+        let (_, record) = gate
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "pay", "payments:send")
+            .unwrap();
+        assert_eq!(
+            record.remaining_budget,
+            Some(Budget::Spend(Price::from(100)))
+        );
+        assert!(matches!(
+            gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "pay", "payments:send"),
+            Err(ExecutionGateError::BudgetExhausted(_))
+        ));
+
+        assert!(matches!(
+            au.clone().with_budget(Budget::Spend(Price::from(100))),
+            Err(AuthorityError::InvalidBudget(_))
+        ));
+        assert!(matches!(
+            au.clone()
+                .with_budget(Budget::Spend(Price::new(500, "USD"))),
+            Err(AuthorityError::InvalidBudget(_))
+        ));
+        let free = AuthorityUnit::new(
+            "test-456".to_string(),
+            "payments:*".to_string(),
+            vec!["root".to_string()],
+            0,
+            1640995200.0,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            free.with_budget(Budget::Spend(Price::from(100))),
+            Err(AuthorityError::InvalidBudget(_))
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_async_executions_can_be_spawned() {
+        let gate = Arc::new(ExecutionGate::new(|_| true));
+        let unit = |id: &str| {
+            AuthorityUnit::new(
+                id.to_string(),
+                "http:get".to_string(),
+                vec!["root".to_string()],
+                10,
+                1640995200.0,
+                None,
+            )
+            .unwrap()
+        };
+
+        let executed = tokio::spawn({
+            let (gate, au) = (gate.clone(), unit("a"));
+            async move {
+                gate.execute_with_authority_async(
+                    &au,
+                    || async {
+                        tokio::task::yield_now().await;
+                        Ok("200 OK".to_string())
+                    },
+                    "fetch",
+                    "http:get",
+                )
+                .await
+            }
+        });
+        let compensated = tokio::spawn({
+            let (gate, au) = (gate.clone(), unit("b"));
+            async move {
+                gate.execute_with_compensation_async(
+                    &au,
+                    || async {
+                        tokio::task::yield_now().await;
+                        Err("timeout".to_string())
+                    },
+                    &|_| Ok("cancelled request".to_string()),
+                    "fetch",
+                    "http:get",
+                )
+                .await
+            }
+        });
+
+        assert_eq!(executed.await.unwrap().unwrap().0.result, "200 OK");
+        assert!(matches!(
+            compensated.await.unwrap(),
+            Err(ExecutionGateError::ActionFailed(_))
+        ));
+        assert!(gate.is_consumed("a").unwrap());
+        assert!(!gate.is_consumed("b").unwrap());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_gate_commits_and_rolls_back() {
+        let gate = ExecutionGate::new(|_| true);
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "http:get".to_string(),
+            vec!["root".to_string()],
+            10,
+            1640995200.0,
+            None,
+        )
+        .unwrap();
+
+        let failed = gate
+            .execute_with_authority_async(
+                &au,
+                || async {
+                    tokio::task::yield_now().await;
+                    Err("timeout".to_string())
+                },
+                "fetch",
+                "http:get",
+            )
+            .await;
+        assert!(matches!(failed, Err(ExecutionGateError::ActionFailed(_))));
+        assert!(!gate.is_consumed(&au.id).unwrap());
+
+        let compensated = std::sync::Mutex::new(None);
+        let failed = gate
+            .execute_with_compensation_async(
+                &au,
+                || async { Err("timeout".to_string()) },
+                &|failure| {
+                    *compensated.lock().unwrap() = Some(failure.to_string());
+                    Ok("cancelled request".to_string())
+                },
+                "fetch",
+                "http:get",
+            )
+            .await;
+        assert!(matches!(failed, Err(ExecutionGateError::ActionFailed(_))));
+        assert_eq!(compensated.lock().unwrap().as_deref(), Some("timeout"));
+        assert!(!gate.is_consumed(&au.id).unwrap());
+
+        let (trace, liability) = gate
+            .execute_with_authority_async(
+                &au,
+                || async {
+                    tokio::task::yield_now().await;
+                    // Reserved while in flight, and the store is not locked.
+                    assert!(gate.is_consumed("test-123").unwrap());
+                    Ok("200 OK".to_string())
+                },
+                "fetch",
+                "http:get",
+            )
+            .await
+            .unwrap();
+        assert_eq!(trace.result, "200 OK");
+        assert_eq!(liability.price, Price::from(10));
+        assert!(matches!(
+            gate.execute_with_authority_async(
+                &au,
+                || async { Ok("200 OK".to_string()) },
+                "fetch",
+                "http:get"
+            )
+            .await,
+            Err(ExecutionGateError::AlreadyConsumed(_))
+        ));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct RowsAffected(u64);
+
+    impl TraceSummary for RowsAffected {
+        fn trace_summary(&self) -> String {
+            format!("rows_affected={}", self.0)
+        }
+
+        fn priced_output(&self) -> Option<&dyn std::any::Any> {
+            Some(self)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum DbError {
+        Deadlock,
+    }
+
+    impl std::fmt::Display for DbError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("deadlock detected")
+        }
+    }
+
+    #[test]
+    fn test_typed_execution_returns_value_and_summary() {
+        let gate = ExecutionGate::new(|_| true);
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "db:orders:write".to_string(),
+            vec!["root".to_string()],
+            10,
+            1640995200.0,
+            None,
+        )
+        .unwrap();
+
+        let failed = gate.execute_typed::<RowsAffected, DbError>(
+            &au,
+            &|| Err(DbError::Deadlock),
+            "update_orders",
+            "db:orders:write",
+        );
+        assert!(matches!(
+            failed,
+            Err(ActionError::Action(DbError::Deadlock))
+        ));
+        assert!(!gate.is_consumed(&au.id).unwrap());
+
+        let (rows, trace, liability) = gate
+            .execute_typed::<_, DbError>(
+                &au,
+                &|| Ok(RowsAffected(3)),
+                "update_orders",
+                "db:orders:write",
+            )
+            .unwrap();
+        assert_eq!(rows, RowsAffected(3));
+        assert_eq!(trace.result, "rows_affected=3");
+        assert_eq!(liability.trace_id, trace.id);
+
+        let refused = gate.execute_typed::<RowsAffected, DbError>(
+            &au,
+            &|| Ok(RowsAffected(1)),
+            "update_orders",
+            "db:orders:write",
+        );
+        assert!(matches!(
+            refused,
+            Err(ActionError::Gate(ExecutionGateError::AlreadyConsumed(_)))
+        ));
+        assert_eq!(vec![1u8, 2].trace_summary().len(), "sha256:".len() + 64);
+    }
+
+    #[test]
+    fn test_denied_and_failed_attempts_are_traced() {
+        let sink = Arc::new(MemoryTraceSink::new());
+        let gate = ExecutionGate::new(|au: &AuthorityUnit| au.id != "revoked")
+            .with_sink(sink.clone())
+            .unwrap();
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            1640995200.0,
+            None,
+        )
+        .unwrap();
+        let revoked = AuthorityUnit {
+            id: "revoked".to_string(),
+            ..au.clone()
+        };
+
+        let _ = gate.execute_with_authority(&revoked, &|| Ok("ok".to_string()), "a", "read");
+        let _ = gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "b", "write");
+        let _ = gate.execute_with_authority(&au, &|| Err("boom".to_string()), "c", "read");
+        let (executed, _) = gate
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "d", "read")
+            .unwrap();
+        let _ = gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "e", "read");
+
+        let log = sink.trace_log().unwrap();
+        assert!(log.verify_links().is_ok());
+        assert_eq!(sink.liabilities().unwrap().len(), 1);
+        let summary: Vec<(&str, TraceOutcome, Option<&str>)> = log
+            .entries()
+            .iter()
+            .map(|t| (t.action_name.as_str(), t.outcome, t.error_kind.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a", TraceOutcome::Denied, Some("invalid_authority")),
+                ("b", TraceOutcome::Denied, Some("scope_mismatch")),
+                ("c", TraceOutcome::Admitted, None),
+                ("c", TraceOutcome::Failed, Some("action_failed")),
+                ("d", TraceOutcome::Admitted, None),
+                ("d", TraceOutcome::Executed, None),
+                ("e", TraceOutcome::Denied, Some("already_consumed")),
+            ]
+        );
+        assert_eq!(log.entries()[0].authority_id, "revoked");
+        assert_eq!(log.entries()[3].result, "boom");
+        assert_eq!(log.entries()[5], executed);
+    }
+
+    #[test]
+    fn test_jsonl_sink_persists_and_resumes_chain() {
+        let path = temp_path("traces.jsonl");
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            1640995200.0,
+            None,
+        )
+        .unwrap()
+        .with_budget(Budget::Uses(5))
+        .unwrap();
+
+        let head = {
+            let gate = ExecutionGate::new(|_| true)
+                .with_sink(Arc::new(JsonlFileSink::open(&path).unwrap()))
+                .unwrap();
+            gate.execute_with_authority(&au, &|| Ok("one".to_string()), "a", "read")
+                .unwrap();
+            let _ = gate.execute_with_authority(&au, &|| Ok("x".to_string()), "b", "write");
+            gate.trace_head().unwrap().unwrap()
+        };
+
+        let gate = ExecutionGate::new(|_| true)
+            .with_sink(Arc::new(JsonlFileSink::open(&path).unwrap()))
+            .unwrap();
+        let (trace, liability) = gate
+            .execute_with_authority(&au, &|| Ok("two".to_string()), "c", "read")
+            .unwrap();
+        assert_eq!(trace.sequence, 4);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[2].contains(r#""liability":null"#));
+        assert!(lines[3].contains(&head));
+        assert_eq!(
+            lines[4],
+            SinkRecord {
+                trace,
+                liability: Some(liability)
+            }
+            .canonical_json()
+        );
+
+        // A torn line left by a failed write is cut before the next append.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(br#"{"liability":nu"#)
+            .unwrap();
+        gate.execute_with_authority(&au, &|| Ok("three".to_string()), "d", "read")
+            .unwrap();
+        drop(gate);
+        let sink = JsonlFileSink::open(&path).unwrap();
+        assert_eq!(sink.resume().unwrap().next_sequence, 7);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents
+            .lines()
+            .all(|line| serde_json::from_str::<serde_json::Value>(line).is_ok()));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sink_failure_before_action_rolls_back_consumption() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let gate = ExecutionGate::new(|_| true)
+            .with_sink(Arc::new(ChannelSink::new(sender)))
+            .unwrap();
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            1640995200.0,
+            None,
+        )
+        .unwrap();
+        let ran = std::sync::atomic::AtomicUsize::new(0);
+        let action = || {
+            ran.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok("ok".to_string())
+        };
+
+        gate.execute_with_authority(&au, &action, "a", "read")
+            .unwrap();
+        assert_eq!(
+            receiver.try_recv().unwrap().trace.outcome,
+            TraceOutcome::Admitted
+        );
+        let record = receiver.try_recv().unwrap();
+        assert_eq!(record.trace.outcome, TraceOutcome::Executed);
+        assert!(record.liability.is_some());
+        drop(receiver);
+
+        // Without an admitted trace the action never runs.
+        let other = AuthorityUnit {
+            id: "test-456".to_string(),
+            ..au.clone()
+        };
+        assert!(matches!(
+            gate.execute_with_authority(&other, &action, "a", "read"),
+            Err(ExecutionGateError::SinkFailed(_))
+        ));
+        assert!(!gate.is_consumed(&other.id).unwrap());
+        assert_eq!(ran.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // Once the action has run, the unit stays consumed.
+        let refusing = ExecutionGate::new(|_| true)
+            .with_sink(Arc::new(CallbackSink::new(|trace, _| {
+                if trace.outcome == TraceOutcome::Executed {
+                    Err(SinkError::Rejected("audit store offline".to_string()))
+                } else {
+                    Ok(())
+                }
+            })))
+            .unwrap();
+        assert!(matches!(
+            refusing.execute_with_authority(&au, &action, "a", "read"),
+            Err(ExecutionGateError::SinkFailed(_))
+        ));
+        assert_eq!(ran.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert!(refusing.is_consumed(&au.id).unwrap());
+        assert!(matches!(
+            refusing.execute_with_authority(&au, &action, "a", "read"),
+            Err(ExecutionGateError::AlreadyConsumed(_))
+        ));
+    }
+
+    #[test]
+    fn test_manual_clock_drives_validation_and_stamps() {
+        let clock = Arc::new(ManualClock::new(1640995200.0));
+        let manager = Arc::new(AuthorityManager::with_max_age(60).with_clock(clock.clone()));
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            1640995200.0,
+            None,
+        )
+        .unwrap();
+        manager.issue_authority(au.clone()).unwrap();
+
+        clock.advance(30.0);
+        assert!(manager.validate_authority(&au));
+        let validator = Arc::clone(&manager);
+        let gate = ExecutionGate::new(move |au| validator.validate_authority(au))
+            .with_clock(clock.clone());
+        let (trace, liability) = gate
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read")
+            .unwrap();
+        assert_eq!(trace.timestamp, 1640995230.0);
+        assert_eq!(liability.timestamp, 1640995230.0);
+
+        clock.set(1640995200.0 + 61.0);
+        assert!(!manager.validate_authority(&au));
+        let revocation = manager.revoke_authority("test-123", "expired").unwrap();
+        assert_eq!(revocation.timestamp, 1640995261.0);
+    }
+
+    #[test]
+    fn test_fixed_clock_makes_traces_time_independent() {
+        let run = || {
+            let gate = ExecutionGate::new(|_| true).with_clock(Arc::new(FixedClock(1000.0)));
+            let au = AuthorityUnit::new(
+                "test-123".to_string(),
+                "read".to_string(),
+                vec!["root".to_string()],
+                10,
+                900.0,
+                None,
+            )
+            .unwrap();
+            let _ = gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "write");
+            gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read")
+                .unwrap()
+        };
+        let (first, _) = run();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let (second, _) = run();
+        assert_eq!(first.timestamp, second.timestamp);
+        assert_eq!(first.sequence, second.sequence);
+    }
+
+    #[test]
+    fn test_content_derived_ids_are_reproducible() {
+        let run = |strategy: IdStrategy| {
+            let gate = ExecutionGate::new(|_| true)
+                .with_clock(Arc::new(FixedClock(1000.0)))
+                .with_id_strategy(strategy);
+            let au = AuthorityUnit::new(
+                "test-123".to_string(),
+                "read".to_string(),
+                vec!["root".to_string()],
+                10,
+                900.0,
+                None,
+            )
+            .unwrap();
+            gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read")
+                .unwrap()
+        };
+
+        let (first_dt, first_lr) = run(IdStrategy::ContentDerived);
+        let (second_dt, second_lr) = run(IdStrategy::ContentDerived);
+        assert_eq!(first_dt.id, second_dt.id);
+        assert_eq!(first_dt.hash(), second_dt.hash());
+        assert_eq!(first_lr.id, second_lr.id);
+        assert_eq!(first_lr.trace_id, first_dt.id);
+        assert_eq!(first_lr.hash(), second_lr.hash());
+        assert_ne!(first_lr.id, first_dt.id);
+
+        let (random_dt, random_lr) = run(IdStrategy::Random);
+        let (other_dt, _) = run(IdStrategy::Random);
+        assert_ne!(random_dt.id, other_dt.id);
+        assert_eq!(random_lr.trace_id, random_dt.id);
+    }
+
+    #[test]
+    fn test_replay_reproduces_recorded_decisions() {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let manager = Arc::new(AuthorityManager::new().with_clock(clock.clone()));
+        let sink = Arc::new(MemoryTraceSink::new());
+        let validator = {
+            let manager = manager.clone();
+            move |au: &AuthorityUnit| manager.validate_authority(au)
+        };
+        let gate = ExecutionGate::new(validator)
+            .with_clock(clock.clone())
+            .with_id_strategy(IdStrategy::ContentDerived)
+            .with_sink(sink.clone())
+            .unwrap();
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            1000.0,
+            None,
+        )
+        .unwrap()
+        .with_budget(Budget::Uses(3))
+        .unwrap();
+
+        let mut events = vec![ReplayEvent::Issue {
+            timestamp: 1000.0,
+            authority: au.clone(),
+        }];
+        manager.issue_authority(au.clone()).unwrap();
+        for (timestamp, scope) in [(1001.0, "read"), (1002.0, "write")] {
+            clock.set(timestamp);
+            let _ = gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "a", scope);
+            events.push(ReplayEvent::Execute(ExecutionRequest {
+                timestamp,
+                authority: au.clone(),
+                action_name: "a".to_string(),
+                action_scope: scope.to_string(),
+                action_output: Ok("ok".to_string()),
+                compensation_output: None,
+            }));
+        }
+        clock.set(1003.0);
+        manager.revoke_authority("test-123", "compromised").unwrap();
+        events.push(ReplayEvent::RevokeAuthority {
+            timestamp: 1003.0,
+            authority_id: "test-123".to_string(),
+            reason: "compromised".to_string(),
+        });
+        clock.set(1004.0);
+        let _ = gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read");
+        events.push(ReplayEvent::Execute(ExecutionRequest {
+            timestamp: 1004.0,
+            authority: au.clone(),
+            action_name: "a".to_string(),
+            action_scope: "read".to_string(),
+            action_output: Ok("ok".to_string()),
+            compensation_output: None,
+        }));
+
+        let recorded = sink.trace_log().unwrap().entries().to_vec();
+        let replayer = Replayer::new(3600).with_id_strategy(IdStrategy::ContentDerived);
+        let report = replayer.replay(&events, &recorded).unwrap();
+        assert!(report.is_consistent(), "{:?}", report.divergences);
+        assert_eq!(report.replayed, recorded);
+
+        let mut tampered = recorded.clone();
+        tampered[2].outcome = TraceOutcome::Executed;
+        let report = replayer.replay(&events, &tampered).unwrap();
+        assert!(report.divergences.contains(&Divergence::Field {
+            index: 2,
+            field: "outcome",
+            recorded: "executed".to_string(),
+            replayed: "denied".to_string(),
+        }));
+        assert!(report.chain_error.is_some());
+
+        let without_revocation: Vec<ReplayEvent> = events
+            .iter()
+            .filter(|event| !matches!(event, ReplayEvent::RevokeAuthority { .. }))
+            .cloned()
+            .collect();
+        let report = replayer.replay(&without_revocation, &recorded).unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.replayed[4].outcome, TraceOutcome::Executed);
+    }
+
+    #[test]
+    fn test_replay_applies_issuance_rules() {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let key = signing_key(3);
+        let read_only = IssuanceRule::ScopeAllowList {
+            issuer: "agent-x".to_string(),
+            scopes: vec!["read:*".to_string()],
+        };
+        let manager = Arc::new(AuthorityManager::with_max_age(3600).with_clock(clock.clone()));
+        manager
+            .trust_issuer("agent-x", key.verifying_key())
+            .unwrap();
+        manager
+            .add_issuance_rule("agent-x-read-only", read_only.clone())
+            .unwrap();
+        let sink = Arc::new(MemoryTraceSink::new());
+        let validator = {
+            let manager = manager.clone();
+            move |au: &AuthorityUnit| manager.validate_authority(au)
+        };
+        let gate = ExecutionGate::new(validator)
+            .with_clock(clock.clone())
+            .with_sink(sink.clone())
+            .unwrap();
+
+        let mut events = Vec::new();
+        for (timestamp, id, scope) in [(1001.0, "a", "read:orders"), (1002.0, "b", "write:orders")]
+        {
+            let mut au = AuthorityUnit::new(
+                id.to_string(),
+                scope.to_string(),
+                vec!["agent-x".to_string()],
+                10,
+                1000.0,
+                None,
+            )
+            .unwrap();
+            au.sign("agent-x", &key);
+            clock.set(timestamp);
+            let _ = gate.execute_with_authority(&au, &|| Ok("ok".to_string()), "a", scope);
+            events.push(ReplayEvent::Execute(ExecutionRequest {
+                timestamp,
+                authority: au,
+                action_name: "a".to_string(),
+                action_scope: scope.to_string(),
+                action_output: Ok("ok".to_string()),
+                compensation_output: None,
+            }));
+        }
+        let recorded = sink.trace_log().unwrap().entries().to_vec();
+        assert_eq!(recorded.last().unwrap().outcome, TraceOutcome::Denied);
+
+        let replayer = Replayer::new(3600).trust_issuer("agent-x", key.verifying_key());
+        let report = replayer
+            .with_issuance_rule("agent-x-read-only", read_only)
+            .replay(&events, &recorded)
+            .unwrap();
+        assert!(report.is_consistent(), "{:?}", report.divergences);
+        let replayer = Replayer::new(3600).trust_issuer("agent-x", key.verifying_key());
+        assert!(!replayer.replay(&events, &recorded).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_replay_reproduces_pricing_failures() {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let manager = Arc::new(AuthorityManager::with_max_age(3600).with_clock(clock.clone()));
+        let sink = Arc::new(MemoryTraceSink::new());
+        let validator = {
+            let manager = manager.clone();
+            move |au: &AuthorityUnit| manager.validate_authority(au)
+        };
+        let foreign_unit: Arc<dyn Pricer> =
+            Arc::new(|context: &PricingContext| match context.result {
+                "0 rows" => Price::new(0, "credits"),
+                _ => Price::new(1, "USD"),
+            });
+        let gate = ExecutionGate::new(validator)
+            .with_clock(clock.clone())
+            .with_sink(sink.clone())
+            .unwrap()
+            .with_pricer(foreign_unit.clone());
+
+        let mut events = Vec::new();
+        for (timestamp, id, output) in [(1001.0, "a", "0 rows"), (1002.0, "b", "3 rows")] {
+            let au = AuthorityUnit::new(
+                id.to_string(),
+                "db:write".to_string(),
+                vec!["root".to_string()],
+                Price::new(10, "credits"),
+                1000.0,
+                None,
+            )
+            .unwrap();
+            manager.issue_authority(au.clone()).unwrap();
+            events.push(ReplayEvent::Issue {
+                timestamp: 1000.0,
+                authority: au.clone(),
+            });
+            clock.set(timestamp);
+            let _ = gate.execute_with_compensation(
+                &au,
+                &|| Ok(output.to_string()),
+                &|_| Ok("rolled back".to_string()),
+                "update",
+                "db:write",
+            );
+            events.push(ReplayEvent::Execute(ExecutionRequest {
+                timestamp,
+                authority: au,
+                action_name: "update".to_string(),
+                action_scope: "db:write".to_string(),
+                action_output: Ok(output.to_string()),
+                compensation_output: Some(Ok("rolled back".to_string())),
+            }));
+        }
+        let recorded = sink.trace_log().unwrap().entries().to_vec();
+        let kinds: Vec<Option<&str>> = recorded.iter().map(|dt| dt.error_kind.as_deref()).collect();
+        assert!(kinds.contains(&Some("pricing_failed")));
+        assert_eq!(recorded.last().unwrap().outcome, TraceOutcome::Compensated);
+
+        let report = Replayer::new(3600)
+            .with_pricer(foreign_unit)
+            .replay(&events, &recorded)
+            .unwrap();
+        assert!(report.is_consistent(), "{:?}", report.divergences);
+        let report = Replayer::new(3600).replay(&events, &recorded).unwrap();
+        assert!(!report.is_consistent());
+    }
+
+    #[test]
+    fn test_replay_reproduces_reservations() {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let manager = Arc::new(AuthorityManager::new().with_clock(clock.clone()));
+        let sink = Arc::new(MemoryTraceSink::new());
+        let validator = {
+            let manager = manager.clone();
+            move |au: &AuthorityUnit| manager.validate_authority(au)
+        };
+        let gate = ExecutionGate::new(validator)
+            .with_clock(clock.clone())
+            .with_id_strategy(IdStrategy::ContentDerived)
+            .with_sink(sink.clone())
+            .unwrap()
+            .with_reservation_timeout(10.0)
+            .unwrap();
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            10,
+            1000.0,
+            None,
+        )
+        .unwrap()
+        .with_budget(Budget::Uses(3))
+        .unwrap();
+        manager.issue_authority(au.clone()).unwrap();
+        let mut events = vec![ReplayEvent::Issue {
+            timestamp: 1000.0,
+            authority: au.clone(),
+        }];
+        let reserve = |events: &mut Vec<ReplayEvent>, timestamp: f64, compensated: bool| {
+            clock.set(timestamp);
+            let reservation = if compensated {
+                gate.reserve_with_compensation(
+                    &au,
+                    Arc::new(|_: &str| Ok("undone".to_string())),
+                    "a",
+                    "read",
+                )
+            } else {
+                gate.reserve(&au, "a", "read")
+            }
+            .unwrap();
+            events.push(ReplayEvent::Reserve {
+                timestamp,
+                reservation_id: reservation.id.clone(),
+                authority: au.clone(),
+                action_name: "a".to_string(),
+                action_scope: "read".to_string(),
+                compensation_output: compensated.then(|| Ok("undone".to_string())),
+            });
+            reservation.id
+        };
+
+        let committed = reserve(&mut events, 1001.0, false);
+        clock.set(1002.0);
+        gate.commit_reservation(&committed, "ok".to_string())
+            .unwrap();
+        events.push(ReplayEvent::CommitReservation {
+            timestamp: 1002.0,
+            reservation_id: committed.clone(),
+            output: "ok".to_string(),
+        });
+        let aborted = reserve(&mut events, 1003.0, true);
+        clock.set(1004.0);
+        gate.abort_reservation(&aborted, "worker crashed").unwrap();
+        events.push(ReplayEvent::AbortReservation {
+            timestamp: 1004.0,
+            reservation_id: aborted,
+            reason: "worker crashed".to_string(),
+        });
+        reserve(&mut events, 1005.0, true);
+        clock.set(1020.0);
+        assert_eq!(gate.release_expired().unwrap(), 1);
+        events.push(ReplayEvent::ReleaseExpired { timestamp: 1020.0 });
+        let _ = gate.commit_reservation(&committed, "again".to_string());
+        events.push(ReplayEvent::CommitReservation {
+            timestamp: 1020.0,
+            reservation_id: committed,
+            output: "again".to_string(),
+        });
+
+        let recorded = sink.trace_log().unwrap().entries().to_vec();
+        assert!(recorded
+            .iter()
+            .any(|trace| trace.error_kind.as_deref() == Some("reservation_expired")));
+        let replayer = Replayer::new(3600)
+            .with_id_strategy(IdStrategy::ContentDerived)
+            .with_reservation_timeout(10.0);
+        let report = replayer.replay(&events, &recorded).unwrap();
+        assert!(report.is_consistent(), "{:?}", report.divergences);
+        assert_eq!(report.replayed, recorded);
+
+        let report = Replayer::new(3600)
+            .with_id_strategy(IdStrategy::ContentDerived)
+            .replay(&events, &recorded)
+            .unwrap();
+        assert!(!report.is_consistent());
+    }
+
+    #[test]
+    fn test_apportionment_line_items_sum_exactly() {
+        let chain: Vec<String> = ["root", "agent", "sub-agent"]
+            .iter()
+            .map(|party| party.to_string())
+            .collect();
+        let amounts = |policy: Apportionment, price: i64| -> Vec<i64> {
+            let price = Price::new(price, "USD");
+            let lines = policy.apportion("root", &chain, &price).unwrap();
+            let total = Price::checked_sum("USD", lines.iter().map(|line| &line.amount));
+            assert_eq!(total, Ok(price));
+            lines.into_iter().map(|line| line.amount.amount).collect()
+        };
+
+        assert_eq!(amounts(Apportionment::IssuerTakesAll, 10), vec![10]);
+        assert_eq!(amounts(Apportionment::EqualSplit, 10), vec![4, 3, 3]);
+        assert_eq!(
+            amounts(Apportionment::Weighted(vec![1, 1, 2]), 7),
+            vec![2, 2, 3]
+        );
+        assert_eq!(
+            amounts(Apportionment::Weighted(vec![0, 0, 5]), 9),
+            vec![0, 0, 9]
+        );
+
+        let repeated = vec!["root".to_string(), "agent".to_string(), "root".to_string()];
+        let lines = Apportionment::EqualSplit
+            .apportion("root", &repeated, &Price::from(10))
+            .unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                LiabilityLine {
+                    party: "root".to_string(),
+                    amount: Price::from(7)
+                },
+                LiabilityLine {
+                    party: "agent".to_string(),
+                    amount: Price::from(3)
+                },
+            ]
+        );
+
+        // Weights are positional from the issuer; a short list repeats its
+        // last weight and a long one is cut to the chain.
+        assert_eq!(
+            amounts(Apportionment::Weighted(vec![2, 1]), 8),
+            vec![4, 2, 2]
+        );
+        assert_eq!(
+            amounts(Apportionment::Weighted(vec![1, 1, 2, 5]), 8),
+            vec![2, 2, 4]
+        );
+        assert_eq!(
+            Apportionment::Weighted(vec![3, 1]).apportion("root", &chain[..1], &Price::from(10)),
+            Ok(vec![LiabilityLine {
+                party: "root".to_string(),
+                amount: Price::from(10)
+            }])
+        );
+        assert_eq!(
+            Apportionment::Weighted(Vec::new()).apportion("root", &chain, &Price::from(10)),
+            Err(ApportionmentError::NoWeights)
+        );
+        assert_eq!(
+            Apportionment::Weighted(vec![0, 0, 0]).apportion("root", &chain, &Price::from(10)),
+            Err(ApportionmentError::ZeroWeights)
+        );
+
+        // The issuer that signed the unit is charged, even when it is not
+        // the first principal of the chain.
+        assert_eq!(
+            Apportionment::IssuerTakesAll.apportion("issuer-co", &chain, &Price::from(10)),
+            Ok(vec![LiabilityLine {
+                party: "issuer-co".to_string(),
+                amount: Price::from(10)
+            }])
+        );
+    }
+
+    #[test]
+    fn test_gate_apportions_liability_across_delegation_chain() {
+        let au = delegated_authority("read", 50);
+        let gate = ExecutionGate::new(|_| true).with_apportionment(Apportionment::EqualSplit);
+        let (_, lr) = gate
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read")
+            .unwrap();
+        let parties: Vec<(&str, i64)> = lr
+            .line_items
+            .iter()
+            .map(|line| (line.party.as_str(), line.amount.amount))
+            .collect();
+        assert_eq!(parties, vec![("root", 4), ("agent", 3), ("sub-agent", 3)]);
+
+        let gate = ExecutionGate::new(|_| true)
+            .with_apportionment(Apportionment::Weighted(vec![2, 1, 1, 1]));
+        let (_, lr) = gate
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read")
+            .unwrap();
+        let amounts: Vec<i64> = lr
+            .line_items
+            .iter()
+            .map(|line| line.amount.amount)
+            .collect();
+        assert_eq!(amounts, vec![5, 3, 2]);
+
+        let gate =
+            ExecutionGate::new(|_| true).with_apportionment(Apportionment::Weighted(Vec::new()));
+        let au = delegated_authority("read", 50);
+        let err = gate
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read")
+            .unwrap_err();
+        assert_eq!(err.kind(), "apportionment_failed");
+        assert!(!gate.is_consumed(&au.id).unwrap());
+    }
+
+    #[test]
+    fn test_liability_ledger_settles_periods() {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let ledger = LiabilityLedger::new(Price::UNITLESS).with_clock(clock.clone());
+        let gate = ExecutionGate::new(|_| true)
+            .with_clock(clock.clone())
+            .with_apportionment(Apportionment::EqualSplit);
+        let au = delegated_authority("read", 50)
+            .with_budget(Budget::Uses(3))
+            .unwrap();
+        for _ in 0..2 {
+            let (_, lr) = gate
+                .execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read")
+                .unwrap();
+            ledger.post(&lr).unwrap();
+            assert_eq!(ledger.post(&lr), Err(LedgerError::DuplicateRecord(lr.id)));
+        }
+        assert_eq!(ledger.party_balance("root").unwrap(), Price::from(8));
+        assert_eq!(ledger.party_balance("agent").unwrap(), Price::from(6));
+        assert_eq!(ledger.scope_balance("read").unwrap(), Price::from(20));
+        ledger.check_invariants().unwrap();
+
+        clock.set(2000.0);
+        let first = ledger.close_period().unwrap();
+        assert_eq!(
+            (first.period, first.opened_at, first.closed_at),
+            (0, 1000.0, 2000.0)
+        );
+        assert_eq!(first.total(), Ok(Price::from(20)));
+        assert_eq!(first.postings.len(), 8);
+        assert!(ledger.party_balances().unwrap().is_empty());
+
+        let (_, lr) = gate
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read")
+            .unwrap();
+        ledger.post(&lr).unwrap();
+        let unbalanced =
+            LiabilityRecord::new("t".to_string(), au.id.clone(), 10, "read".to_string());
+        assert!(matches!(
+            ledger.post(&unbalanced),
+            Err(LedgerError::Unbalanced { apportioned, .. }) if apportioned == Price::from(0)
+        ));
+        clock.set(3000.0);
+        let second = ledger.close_period().unwrap();
+        assert_eq!(second.prev_hash, Some(first.hash()));
+        assert_eq!(
+            second.party_balances.get("sub-agent"),
+            Some(&Price::from(3))
+        );
+        ledger.check_invariants().unwrap();
+
+        let mut statements = ledger.statements().unwrap();
+        let head = ledger.statement_head().unwrap().unwrap();
+        LiabilityLedger::verify_statements_head(&statements, &head).unwrap();
+        // Only the anchored head reveals a dropped or altered last statement.
+        let mut truncated = statements.clone();
+        truncated.pop();
+        LiabilityLedger::verify_statements(&truncated).unwrap();
+        assert!(matches!(
+            LiabilityLedger::verify_statements_head(&truncated, &head),
+            Err(LedgerError::HeadMismatch { .. })
+        ));
+        let mut altered = statements.clone();
+        altered[1].closed_at += 1.0;
+        assert!(matches!(
+            LiabilityLedger::verify_statements_head(&altered, &head),
+            Err(LedgerError::HeadMismatch { .. })
+        ));
+        statements[0]
+            .party_balances
+            .insert("root".to_string(), Price::from(0));
+        assert!(matches!(
+            LiabilityLedger::verify_statements(&statements),
+            Err(LedgerError::InvariantViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_prices_refuse_to_mix_units() {
+        let usd = Price::new(250, "USD");
+        let credits = Price::new(3, "credits");
+        assert_eq!(
+            usd.checked_add(&Price::new(50, "USD")),
+            Ok(Price::new(300, "USD"))
+        );
+        assert_eq!(
+            usd.checked_add(&credits),
+            Err(PriceError::UnitMismatch {
+                left: "USD".to_string(),
+                right: "credits".to_string()
+            })
+        );
+        assert_eq!(
+            Price::new(i64::MAX, "USD").checked_add(&Price::new(1, "USD")),
+            Err(PriceError::Overflow)
+        );
+        assert_eq!(usd.to_string(), "250 USD");
+
+        let root = DelegationRecord::signed(
+            "test-123",
+            None,
+            "root",
+            "agent",
+            "any",
+            Price::new(1000, "USD"),
+            &signing_key(1),
+        );
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string(), "agent".to_string()],
+            Price::new(100, "credits"),
+            current_timestamp(),
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            au.with_delegations(vec![root]),
+            Err(AuthorityError::DelegationUnitMismatch(1))
+        ));
+
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "read".to_string(),
+            vec!["root".to_string()],
+            Price::new(100, "credits"),
+            current_timestamp(),
+            None,
+        )
+        .unwrap();
+        let (_, lr) = ExecutionGate::new(|_| true)
+            .execute_with_authority(&au, &|| Ok("ok".to_string()), "a", "read")
+            .unwrap();
+        assert_eq!(lr.price, Price::new(100, "credits"));
+        assert_eq!(lr.line_items[0].amount, Price::new(100, "credits"));
+
+        let ledger = LiabilityLedger::new("USD");
+        assert!(matches!(
+            ledger.post(&lr),
+            Err(LedgerError::Price(PriceError::UnitMismatch { .. }))
+        ));
+        LiabilityLedger::new("credits").post(&lr).unwrap();
+    }
+
+    #[test]
+    fn test_pricer_charges_at_most_the_quote() {
+        let path = temp_path("priced-store");
+        let per_row = |context: &PricingContext| {
+            let rows = context
+                .output::<RowsAffected>()
+                .map_or(i64::MAX, |rows| rows.0 as i64);
+            Price::new(rows.saturating_mul(2), "credits")
+        };
+        let au = AuthorityUnit::new(
+            "test-123".to_string(),
+            "db:orders:write".to_string(),
+            vec!["root".to_string()],
+            Price::new(10, "credits"),
+            current_timestamp(),
+            None,
+        )
+        .unwrap()
+        .with_budget(Budget::Spend(Price::new(100, "credits")))
+        .unwrap();
+
+        {
+            let gate = ExecutionGate::new(|_| true)
+                .with_store(Arc::new(FileConsumptionStore::open(&path).unwrap()))
+                .with_pricer(Arc::new(per_row));
+            let (_, _, cheap) = gate
+                .execute_typed::<RowsAffected, DbError>(
+                    &au,
+                    &|| Ok(RowsAffected(3)),
+                    "update_orders",
+                    "db:orders:write",
+                )
+                .unwrap();
+            assert_eq!(cheap.price, Price::new(6, "credits"));
+            assert_eq!(cheap.quoted_price, Some(Price::new(10, "credits")));
+            assert_eq!(cheap.line_items[0].amount, Price::new(6, "credits"));
+            assert_eq!(
+                cheap.remaining_budget,
+                Some(Budget::Spend(Price::new(94, "credits")))
+            );
+
+            let (_, _, capped) = gate
+                .execute_typed::<RowsAffected, DbError>(
+                    &au,
+                    &|| Ok(RowsAffected(40)),
+                    "update_orders",
+                    "db:orders:write",
+                )
+                .unwrap();
+            assert_eq!(capped.price, Price::new(10, "credits"));
+            assert_eq!(
+                capped.remaining_budget,
+                Some(Budget::Spend(Price::new(84, "credits")))
+            );
+        }
+
+        let reopened = FileConsumptionStore::open(&path).unwrap();
+        assert_eq!(
+            reopened.usage(&au.id).unwrap(),
+            Usage {
+                uses: 2,
+                spent: Price::new(16, "credits")
+            }
+        );
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(
+            bounded_charge(&au.price, Price::new(1, "USD")),
+            Err(PriceError::UnitMismatch { .. })
+        ));
+        assert_eq!(
+            bounded_charge(&au.price, Price::new(-5, "credits")),
+            Ok(Price::new(0, "credits"))
+        );
+
+        // A price in the wrong unit fails the execution on the record.
+        let sink = Arc::new(MemoryTraceSink::new());
+        let gate = ExecutionGate::new(|_| true)
+            .with_sink(sink.clone())
+            .unwrap()
+            .with_pricer(Arc::new(|_: &PricingContext| Price::new(1, "USD")));
+        let err = gate
+            .execute_typed::<RowsAffected, DbError>(
+                &au,
+                &|| Ok(RowsAffected(3)),
+                "update_orders",
+                "db:orders:write",
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ActionError::Gate(ExecutionGateError::PricingFailed(_))
+        ));
+        assert!(!gate.is_consumed(&au.id).unwrap());
+        let last = sink.trace_log().unwrap().entries().last().cloned().unwrap();
+        assert_eq!(last.outcome, TraceOutcome::Failed);
+        assert_eq!(last.error_kind.as_deref(), Some("pricing_failed"));
+    }
+
+    #[test]
+    fn test_validity_window_overrides_max_age() {
+        let unit = || {
+            AuthorityUnit::new(
+                "test-123".to_string(),
+                "read".to_string(),
+                vec!["root".to_string()],
+                10,
+                1000.0,
+                None,
+            )
+            .unwrap()
+        };
+
+        let emergency = unit().with_validity(Some(1100.0), Some(1160.0)).unwrap();
+        assert!(!emergency.is_valid(1050.0, 3600));
+        assert!(emergency.is_valid(1100.0, 3600));
+        assert!(!emergency.is_valid(1160.0, 3600));
+
+        let standing = unit()
+            .with_validity(None, Some(1000.0 + 86400.0 * 30.0))
+            .unwrap();
+        assert!(standing.is_valid(1000.0 + 86400.0 * 7.0, 3600));
+        assert!(!unit().is_valid(1000.0 + 86400.0 * 7.0, 3600));
+        assert_ne!(standing.hash(), unit().hash());
+
+        for (not_before, expires_at) in [
+            (Some(1200.0), Some(1100.0)),
+            (None, Some(900.0)),
+            (Some(f64::NAN), None),
+        ] {
+            assert!(matches!(
+                unit().with_validity(not_before, expires_at),
+                Err(AuthorityError::InvalidValidityWindow(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_issuance_rules_name_the_violated_rule() {
+        let manager = AuthorityManager::new();
+        manager
+            .add_issuance_rule(
+                "agent-x-read-only",
+                IssuanceRule::ScopeAllowList {
+                    issuer: "agent-x".to_string(),
+                    scopes: vec!["read:*".to_string()],
+                },
+            )
+            .unwrap();
+        manager
+            .add_issuance_rule("price-cap", IssuanceRule::MaxPrice(Price::from(1000)))
+            .unwrap();
+        manager
+            .add_issuance_rule("shallow-delegation", IssuanceRule::MaxDelegationDepth(3))
+            .unwrap();
+        let unit = |id: &str, scope: &str, chain: &[&str], price: i64| {
+            AuthorityUnit::new(
+                id.to_string(),
+                scope.to_string(),
+                chain
+                    .iter()
+                    .map(|principal| principal.to_string())
+                    .collect(),
+                price,
+                current_timestamp(),
+                None,
+            )
+            .unwrap()
+        };
+        let violated = |result: Result<(), ManagerError>| match result {
+            Err(ManagerError::PolicyViolation { rule, .. }) => rule,
+            other => panic!("expected a policy violation, got {:?}", other),
+        };
+
+        manager
+            .issue_authority(unit("a", "read:orders", &["agent-x"], 10))
+            .unwrap();
+        manager
+            .issue_authority(unit("b", "write:orders", &["agent-y"], 10))
+            .unwrap();
+        assert_eq!(
+            violated(manager.issue_authority(unit("c", "write:orders", &["agent-x"], 10))),
+            "agent-x-read-only"
+        );
+        assert_eq!(
+            violated(manager.issue_authority(unit("d", "read", &["agent-y"], 1001))),
+            "price-cap"
+        );
+        assert_eq!(
+            violated(manager.issue_authority(delegate_through(
+                &manager,
+                unit("e", "read", &["root", "a", "b", "c", "d"], 10)
+            ))),
+            "shallow-delegation"
+        );
+        assert_eq!(
+            violated(manager.issue_authority(delegate_through(
+                &manager,
+                unit("g", "write:orders", &["root", "agent-x"], 10)
+            ))),
+            "agent-x-read-only"
+        );
+        assert!(manager.get_authority("c").is_none());
+        assert!(matches!(
+            manager.add_issuance_rule("price-cap", IssuanceRule::MaxPrice(Price::from(5))),
+            Err(ManagerError::DuplicateRule(_))
+        ));
+
+        // Signed units issued elsewhere are held to the same rules.
+        let key = signing_key(3);
+        manager
+            .trust_issuer("agent-x", key.verifying_key())
+            .unwrap();
+        let mut foreign = unit("f", "write:orders", &["agent-x"], 10);
+        foreign.sign("agent-x", &key);
+        assert!(!manager.validate_authority(&foreign));
+        assert!(manager.remove_issuance_rule("agent-x-read-only").unwrap());
+        assert!(manager.validate_authority(&foreign));
+    }
+
+    #[test]
+    fn test_policy_chain_explains_every_decision() {
+        let ten_am = 10.0 * 86_400.0 + 10.0 * 3600.0;
+        let clock = Arc::new(ManualClock::new(ten_am));
+        let sink = Arc::new(MemoryTraceSink::new());
+        let policy = Arc::new(
+            PolicyChain::new()
+                .with_rule(
+                    "office-hours",
+                    ExecutionRule::TimeOfDay {
+                        start: 9 * 3600,
+                        end: 17 * 3600,
+                    },
+                )
+                .unwrap()
+                .with_rule(
+                    "per-agent",
+                    ExecutionRule::RateLimit {
+                        max_executions: 1,
+                        window_seconds: 60.0,
+                    },
+                )
+                .unwrap()
+                .with_rule(
+                    "per-agent-hourly",
+                    ExecutionRule::RateLimit {
+                        max_executions: 3,
+                        window_seconds: 3600.0,
+                    },
+                )
+                .unwrap()
+                .with_rule(
+                    "read-only",
+                    ExecutionRule::ActionAllowList(vec!["read".to_string()]),
+                )
+                .unwrap()
+                .with_rule(
+                    "spend-cap",
+                    ExecutionRule::MaxCumulativeSpend(Price::from(15)),
+                )
+                .unwrap(),
+        );
+        assert_eq!(
+            policy.add_rule("read-only", ExecutionRule::ActionAllowList(vec![])),
+            Err(PolicyError::DuplicateRule("read-only".to_string()))
+        );
+        for invalid in [
+            ExecutionRule::TimeOfDay {
+                start: 0,
+                end: 90_000,
+            },
+            ExecutionRule::TimeOfDay {
+                start: 3600,
+                end: 3600,
+            },
+            ExecutionRule::RateLimit {
+                max_executions: 0,
+                window_seconds: 60.0,
+            },
+            ExecutionRule::RateLimit {
+                max_executions: 2,
+                window_seconds: f64::NAN,
+            },
+            ExecutionRule::RateLimit {
+                max_executions: 2,
+                window_seconds: -60.0,
+            },
+        ] {
+            assert!(matches!(
+                policy.add_rule("invalid", invalid),
+                Err(PolicyError::InvalidRule(_))
+            ));
+        }
+        assert!(policy
+            .add_rule(
+                "all-day",
+                ExecutionRule::TimeOfDay {
+                    start: 0,
+                    end: 86_400
+                }
+            )
+            .is_ok());
+        assert_eq!(policy.remove_rule("all-day"), Ok(true));
+        let gate = ExecutionGate::new(|_| true)
+            .with_clock(clock.clone())
+            .with_sink(sink.clone())
+            .unwrap()
+            .with_policy(policy.clone());
+        let unit = |id: &str| {
+            AuthorityUnit::new(
+                id.to_string(),
+                "read".to_string(),
+                vec!["root".to_string(), "agent".to_string()],
+                10,
+                ten_am,
+                None,
+            )
+            .unwrap()
+        };
+        let denied_by =
+            |result: Result<(DecisionTrace, LiabilityRecord), ExecutionGateError>| match result {
+                Err(ExecutionGateError::PolicyDenied { rule, .. }) => rule,
+                other => panic!("expected a policy denial, got {:?}", other),
+            };
+        let ok = || Ok("ok".to_string());
+
+        // A failed action returns its spend and its place in the rate windows.
+        let _ =
+            gate.execute_with_authority(&unit("c"), &|| Err("boom".to_string()), "read", "read");
+        assert_eq!(
+            policy.spent("agent", Price::UNITLESS).unwrap(),
+            Price::from(0)
+        );
+
+        assert_eq!(
+            denied_by(gate.execute_with_authority(&unit("b"), &ok, "write", "read")),
+            "read-only"
+        );
+        assert!(!gate.is_consumed("b").unwrap());
+        let (dt, _) = gate
+            .execute_with_authority(&unit("a"), &ok, "read", "read")
+            .unwrap();
+        assert_eq!(dt.policy.len(), 5);
+        assert!(dt.policy.iter().all(|evaluation| evaluation.allowed));
+
+        // Both the rate and the spend limit refuse; the trace lists both.
+        assert_eq!(
+            denied_by(gate.execute_with_authority(&unit("e"), &ok, "read", "read")),
+            "per-agent"
+        );
+        let log = sink.trace_log().unwrap();
+        let denial = log.entries().last().unwrap();
+        assert_eq!(denial.error_kind.as_deref(), Some("policy_denied"));
+        let refused: Vec<&str> = denial
+            .policy
+            .iter()
+            .filter(|evaluation| !evaluation.allowed)
+            .map(|evaluation| evaluation.rule.as_str())
+            .collect();
+        assert_eq!(refused, ["per-agent", "spend-cap"]);
+
+        clock.advance(60.0);
+        assert_eq!(
+            denied_by(gate.execute_with_authority(&unit("e"), &ok, "read", "read")),
+            "spend-cap"
+        );
+        // Each rate limit keeps its own window.
+        let log = sink.trace_log().unwrap();
+        let hourly = &log.entries().last().unwrap().policy[2];
+        assert_eq!(
+            hourly.reason,
+            "1 of 3 executions by 'agent' in the last 3600s"
+        );
+        assert!(policy.remove_rule("spend-cap").unwrap());
+        gate.execute_with_authority(&unit("e"), &ok, "read", "read")
+            .unwrap();
+
+        clock.set(ten_am + 8.0 * 3600.0);
+        assert_eq!(
+            denied_by(gate.execute_with_authority(&unit("f"), &ok, "read", "read")),
+            "office-hours"
+        );
+
+        // Executions only count under rate limit rules that exist.
+        let chain = PolicyChain::new();
+        let au = unit("g");
+        let context = PolicyContext {
+            authority: &au,
+            action_name: "read",
+            action_scope: "read",
+            now: ten_am,
+        };
+        for _ in 0..3 {
+            chain.admit(&context).unwrap();
+        }
+        chain
+            .add_rule(
+                "once",
+                ExecutionRule::RateLimit {
+                    max_executions: 1,
+                    window_seconds: 60.0,
+                },
+            )
+            .unwrap();
+        assert!(chain.admit(&context).unwrap()[0].allowed);
+        assert!(!chain.admit(&context).unwrap()[0].allowed);
+    }
+
+    #[test]
+    fn test_rate_limits_refill_and_adjust_at_runtime() {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let limiter = Arc::new(
+            RateLimiter::new()
+                .with_limit(
+                    RateLimitKey::Principal("agent".to_string()),
+                    RateLimit::new(2, 0.5).unwrap(),
+                )
+                .unwrap()
+                .with_limit(
+                    RateLimitKey::Scope("write:*".to_string()),
+                    RateLimit::new(1, 0.0).unwrap(),
+                )
+                .unwrap(),
+        );
+        assert!(matches!(
+            RateLimit::new(0, 1.0),
+            Err(RateLimitError::InvalidLimit(_))
+        ));
+        let sink = Arc::new(MemoryTraceSink::new());
+        let gate = ExecutionGate::new(|_| true)
+            .with_clock(clock.clone())
+            .with_sink(sink.clone())
+            .unwrap()
+            .with_rate_limiter(limiter.clone());
+        let unit = |id: &str, principal: &str, scope: &str| {
+            AuthorityUnit::new(
+                id.to_string(),
+                scope.to_string(),
+                vec!["root".to_string(), principal.to_string()],
+                1,
+                1000.0,
+                None,
+            )
+            .unwrap()
+        };
+        let run = |au: &AuthorityUnit| {
+            gate.execute_with_authority(au, &|| Ok("ok".to_string()), "a", &au.scope)
+        };
+
+        run(&unit("a", "agent", "read:orders")).unwrap();
+        run(&unit("b", "agent", "read:orders")).unwrap();
+        let limited = unit("c", "agent", "read:orders");
+        match run(&limited) {
+            Err(ExecutionGateError::RateLimited { key, retry_after }) => {
+                assert_eq!(key, RateLimitKey::Principal("agent".to_string()));
+                assert_eq!(retry_after, 2.0);
+            }
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+        assert!(!gate.is_consumed("c").unwrap());
+        let log = sink.trace_log().unwrap();
+        assert_eq!(
+            log.entries().last().unwrap().error_kind.as_deref(),
+            Some("rate_limited")
+        );
+        clock.advance(2.0);
+        run(&limited).unwrap();
+
+        // The scope bucket is shared by every principal writing within it.
+        run(&unit("d", "other", "write:orders")).unwrap();
+        assert_eq!(
+            run(&unit("e", "other", "write:users")).unwrap_err().kind(),
+            "rate_limited"
+        );
+        assert!(limiter
+            .remove_limit(&RateLimitKey::Scope("write:*".to_string()))
+            .unwrap());
+        run(&unit("e", "other", "write:users")).unwrap();
+
+        // Attempts that are denied or rolled back give their token back.
+        limiter
+            .set_limit(
+                RateLimitKey::Principal("flaky".to_string()),
+                RateLimit::new(1, 0.0).unwrap(),
+            )
+            .unwrap();
+        let flaky = unit("f", "flaky", "read:orders");
+        for _ in 0..2 {
+            assert_eq!(
+                gate.execute_with_authority(
+                    &flaky,
+                    &|| Err("timeout".to_string()),
+                    "a",
+                    "read:orders"
+                )
+                .unwrap_err()
+                .kind(),
+                "action_failed"
+            );
+        }
+        let policy = Arc::new(
+            PolicyChain::new()
+                .with_rule(
+                    "only-a",
+                    ExecutionRule::ActionAllowList(vec!["a".to_string()]),
+                )
+                .unwrap(),
+        );
+        let guarded = ExecutionGate::new(|_| true)
+            .with_clock(clock.clone())
+            .with_rate_limiter(limiter.clone())
+            .with_policy(policy);
+        assert_eq!(
+            guarded
+                .execute_with_authority(&flaky, &|| Ok("ok".to_string()), "b", "read:orders")
+                .unwrap_err()
+                .kind(),
+            "policy_denied"
+        );
+        run(&flaky).unwrap();
+        assert_eq!(
+            run(&unit("g", "flaky", "read:orders")).unwrap_err().kind(),
+            "rate_limited"
+        );
+    }
+
+    #[test]
+    fn test_reservations_settle_exactly_once() {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let sink = Arc::new(MemoryTraceSink::new());
+        let gate = ExecutionGate::new(|_| true)
+            .with_clock(clock.clone())
+            .with_sink(sink.clone())
+            .unwrap()
+            .with_reservation_timeout(30.0)
+            .unwrap();
+        let unit = |id: &str| {
+            AuthorityUnit::new(
+                id.to_string(),
+                "read".to_string(),
+                vec!["root".to_string()],
+                5,
+                1000.0,
+                None,
+            )
+            .unwrap()
+        };
+        let last_trace = || sink.trace_log().unwrap().entries().last().unwrap().clone();
+
+        let au = unit("a");
+        let reservation = gate.reserve(&au, "remote", "read").unwrap();
+        assert_eq!(reservation.expires_at, 1030.0);
+        assert!(gate.is_consumed("a").unwrap());
+        assert_eq!(
+            gate.reserve(&au, "remote", "read").err().unwrap().kind(),
+            "already_consumed"
+        );
+        reservation.abort("worker crashed").unwrap();
+        assert!(!gate.is_consumed("a").unwrap());
+        let aborted = last_trace();
+        assert_eq!(aborted.outcome, TraceOutcome::Failed);
+        assert_eq!(aborted.result, "worker crashed");
+
+        let (dt, lr) = gate
+            .reserve(&au, "remote", "read")
+            .unwrap()
+            .commit("done".to_string())
+            .unwrap();
+        assert_eq!(dt.outcome, TraceOutcome::Executed);
+        assert_eq!(dt.result, "done");
+        assert_eq!(lr.price, Price::from(5));
+        assert!(gate.reserve(&au, "remote", "read").is_err());
+
+        // An unsettled reservation is released at its deadline, and a late
+        // commit is refused.
+        let late = unit("b");
+        let reservation = gate.reserve(&late, "remote", "read").unwrap();
+        clock.advance(30.0);
+        assert_eq!(gate.release_expired().unwrap(), 1);
+        assert!(!gate.is_consumed("b").unwrap());
+        assert_eq!(
+            last_trace().error_kind.as_deref(),
+            Some("reservation_expired")
+        );
+        assert!(matches!(
+            reservation.commit("done".to_string()),
+            Err(ExecutionGateError::ReservationExpired(_))
+        ));
+
+        let reservation = gate.reserve(&late, "remote", "read").unwrap();
+        clock.advance(31.0);
+        gate.execute_with_authority(&late, &|| Ok("ok".to_string()), "local", "read")
+            .unwrap();
+        assert!(reservation.commit("done".to_string()).is_err());
+        sink.trace_log().unwrap().verify_links().unwrap();
+
+        // Reservations can be settled by ID alone.
+        let remote = unit("c");
+        let id = gate.reserve(&remote, "remote", "read").unwrap().id;
+        gate.abort_reservation(&id, "worker crashed").unwrap();
+        assert!(!gate.is_consumed("c").unwrap());
+        let id = gate.reserve(&remote, "remote", "read").unwrap().id;
+        let (dt, _) = gate.commit_reservation(&id, "done".to_string()).unwrap();
+        assert_eq!(dt.result, "done");
+        assert!(matches!(
+            gate.abort_reservation(&id, "again"),
+            Err(ExecutionGateError::ReservationSettled(_))
+        ));
+        assert!(matches!(
+            gate.abort_reservation("no-such-reservation", "again"),
+            Err(ExecutionGateError::UnknownReservation(_))
+        ));
+        // Closed reservations are forgotten one timeout later.
+        clock.advance(30.0);
+        assert!(matches!(
+            gate.commit_reservation(&id, "done".to_string()),
+            Err(ExecutionGateError::UnknownReservation(_))
+        ));
+
+        for timeout in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                ExecutionGate::new(|_| true).with_reservation_timeout(timeout),
+                Err(ExecutionGateError::InvalidReservationTimeout(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_reservation_holds_are_released_after_restart() {
+        let path = temp_path("holds.log");
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let unit = |id: &str| {
+            AuthorityUnit::new(
+                id.to_string(),
+                "read".to_string(),
+                vec!["root".to_string()],
+                5,
+                1000.0,
+                None,
+            )
+            .unwrap()
+        };
+        let gate_on = |store: FileConsumptionStore, sink: &Arc<MemoryTraceSink>| {
+            ExecutionGate::new(|_| true)
+                .with_clock(clock.clone())
+                .with_sink(sink.clone())
+                .unwrap()
+                .with_store(Arc::new(store))
+                .with_reservation_timeout(30.0)
+                .unwrap()
+        };
+
+        let sink = Arc::new(MemoryTraceSink::new());
+        let (expiring, aborted) = {
+            let gate = gate_on(FileConsumptionStore::open(&path).unwrap(), &sink);
+            let expiring = gate.reserve(&unit("a"), "remote", "read").unwrap().id;
+            clock.advance(10.0);
+            let aborted = gate.reserve(&unit("b"), "remote", "read").unwrap().id;
+            let settled = gate.reserve(&unit("c"), "remote", "read").unwrap();
+            settled.commit("done".to_string()).unwrap();
+            (expiring, aborted)
+        };
+
+        // The gate is gone, but its holds are still in the store.
+        let store = FileConsumptionStore::open(&path).unwrap();
+        let mut held: Vec<String> = store
+            .holds()
+            .unwrap()
+            .into_iter()
+            .map(|hold| hold.authority_id)
+            .collect();
+        held.sort();
+        assert_eq!(held, ["a", "b"]);
+        let gate = gate_on(store, &sink);
+        assert!(gate.is_consumed("a").unwrap());
+
+        clock.advance(20.0);
+        assert_eq!(gate.release_expired().unwrap(), 1);
+        assert!(!gate.is_consumed("a").unwrap());
+        let expired = sink.trace_log().unwrap().entries().last().unwrap().clone();
+        assert_eq!(expired.authority_id, "a");
+        assert_eq!(expired.error_kind.as_deref(), Some("reservation_expired"));
+        assert!(matches!(
+            gate.commit_reservation(&expiring, "done".to_string()),
+            Err(ExecutionGateError::ReservationExpired(_))
+        ));
+
+        // A recovered hold cannot be committed, but can be aborted.
+        assert!(matches!(
+            gate.commit_reservation(&aborted, "done".to_string()),
+            Err(ExecutionGateError::ReservationRecovered(_))
+        ));
+        gate.abort_reservation(&aborted, "worker lost").unwrap();
+        assert!(!gate.is_consumed("b").unwrap());
+        assert!(gate.is_consumed("c").unwrap());
+        drop(gate);
+
+        let store = FileConsumptionStore::open(&path).unwrap();
+        assert!(store.holds().unwrap().is_empty());
+        assert!(!store.is_consumed("a").unwrap());
+        assert!(store.is_consumed("c").unwrap());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_expired_reservations_do_not_fail_other_admissions() {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let sink = Arc::new(MemoryTraceSink::new());
+        let gate = Arc::new(
+            ExecutionGate::new(|_| true)
+                .with_clock(clock.clone())
+                .with_sink(sink.clone())
+                .unwrap(),
+        );
+        let unit = |id: &str| {
+            AuthorityUnit::new(
+                id.to_string(),
+                "payments".to_string(),
+                vec!["root".to_string()],
+                3,
+                1000.0,
+                None,
+            )
+            .unwrap()
+        };
+
+        let failing: ReservationCompensation = Arc::new(|_| Err("undo failed".to_string()));
+        gate.reserve_with_compensation(&unit("a"), failing, "charge", "payments")
+            .unwrap();
+        // A compensation may call back into the gate.
+        let reentrant: ReservationCompensation = {
+            let gate = gate.clone();
+            let au = unit("b");
+            Arc::new(move |_| {
+                gate.execute_with_authority(&au, &|| Ok("voided".to_string()), "void", "payments")
+                    .map(|(dt, _)| dt.result)
+                    .map_err(|err| err.to_string())
+            })
+        };
+        gate.reserve_with_compensation(&unit("c"), reentrant, "charge", "payments")
+            .unwrap();
+        clock.advance(301.0);
+
+        gate.execute_with_authority(&unit("d"), &|| Ok("ok".to_string()), "charge", "payments")
+            .unwrap();
+        let traces = sink.trace_log().unwrap().entries().to_vec();
+        let failed = traces
+            .iter()
+            .find(|dt| dt.error_kind.as_deref() == Some("compensation_failed"))
+            .unwrap();
+        assert_eq!(failed.authority_id, "a");
+        assert!(gate.is_consumed("a").unwrap());
+        assert!(gate.is_consumed("b").unwrap());
+        assert!(!gate.is_consumed("c").unwrap());
+        assert_eq!(traces.last().unwrap().authority_id, "d");
+        assert_eq!(gate.release_expired().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_compensation_runs_when_an_action_is_rolled_back() {
+        let clock = Arc::new(ManualClock::new(1000.0));
+        let sink = Arc::new(MemoryTraceSink::new());
+        let gate = ExecutionGate::new(|_| true)
+            .with_clock(clock.clone())
+            .with_sink(sink.clone())
+            .unwrap();
+        let unit = |id: &str| {
+            AuthorityUnit::new(
+                id.to_string(),
+                "payments".to_string(),
+                vec!["root".to_string()],
+                3,
+                1000.0,
+                None,
+            )
+            .unwrap()
+        };
+        let failures = std::sync::Mutex::new(Vec::new());
+        let refund = |failure: &str| {
+            failures.lock().unwrap().push(failure.to_string());
+            Ok("refunded card".to_string())
+        };
+        let traces = || sink.trace_log().unwrap().entries().to_vec();
+
+        gate.execute_with_compensation(
+            &unit("a"),
+            &|| Ok("charged card".to_string()),
+            &refund,
+            "charge",
+            "payments",
+        )
+        .unwrap();
+        assert!(failures.lock().unwrap().is_empty());
+
+        let declined = || Err("card declined".to_string());
+        let err = gate
+            .execute_with_compensation(&unit("b"), &declined, &refund, "charge", "payments")
+            .unwrap_err();
+        assert_eq!(err.kind(), "action_failed");
+        assert_eq!(*failures.lock().unwrap(), ["card declined"]);
+        assert!(!gate.is_consumed("b").unwrap());
+        let outcomes: Vec<TraceOutcome> = traces().iter().map(|dt| dt.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [
+                TraceOutcome::Admitted,
+                TraceOutcome::Executed,
+                TraceOutcome::Admitted,
+                TraceOutcome::Failed,
+                TraceOutcome::Compensated
+            ]
+        );
+        assert_eq!(traces()[4].result, "refunded card");
+
+        let stuck = |_: &str| Err("refund endpoint unavailable".to_string());
+        assert!(matches!(
+            gate.execute_with_compensation(&unit("c"), &declined, &stuck, "charge", "payments"),
+            Err(ExecutionGateError::CompensationFailed(_))
+        ));
+        // The action's effects may remain, so the debit stands.
+        assert!(gate.is_consumed("c").unwrap());
+        let last = traces().pop().unwrap();
+        assert_eq!(last.outcome, TraceOutcome::Failed);
+        assert_eq!(last.error_kind.as_deref(), Some("compensation_failed"));
+        assert_eq!(traces()[traces().len() - 2].outcome, TraceOutcome::Admitted);
+
+        // The compensation runs before any trace of the rollback is written,
+        // and a refused compensated trace does not hide the action's error.
+        for refused in [TraceOutcome::Failed, TraceOutcome::Compensated] {
+            failures.lock().unwrap().clear();
+            let refusing = ExecutionGate::new(|_| true)
+                .with_sink(Arc::new(CallbackSink::new(move |trace, _| {
+                    if trace.outcome == refused {
+                        Err(SinkError::Rejected("audit store offline".to_string()))
+                    } else {
+                        Ok(())
+                    }
+                })))
+                .unwrap();
+            let err = refusing
+                .execute_with_compensation(&unit("d"), &declined, &refund, "charge", "payments")
+                .unwrap_err();
+            let expected = match refused {
+                TraceOutcome::Failed => "sink_failed",
+                _ => "action_failed",
+            };
+            assert_eq!(err.kind(), expected);
+            assert_eq!(*failures.lock().unwrap(), ["card declined"]);
+            assert!(!refusing.is_consumed("d").unwrap());
+        }
+
+        // Recorded compensation outputs replay to the same traces.
+        let events: Vec<ReplayEvent> = [
+            (
+                "a",
+                Ok("charged card".to_string()),
+                Ok("refunded card".to_string()),
+            ),
+            (
+                "b",
+                Err("card declined".to_string()),
+                Ok("refunded card".to_string()),
+            ),
+            (
+                "c",
+                Err("card declined".to_string()),
+                Err("refund endpoint unavailable".to_string()),
+            ),
+        ]
+        .into_iter()
+        .map(|(id, action_output, compensation)| {
+            ReplayEvent::Execute(ExecutionRequest {
+                timestamp: 1000.0,
+                authority: unit(id),
+                action_name: "charge".to_string(),
+                action_scope: "payments".to_string(),
+                action_output,
+                compensation_output: Some(compensation),
+            })
+        })
+        .collect();
+        let issued: Vec<ReplayEvent> = ["a", "b", "c"]
+            .into_iter()
+            .map(|id| ReplayEvent::Issue {
+                timestamp: 1000.0,
+                authority: unit(id),
+            })
+            .chain(events)
+            .collect();
+        let report = Replayer::new(3600).replay(&issued, &traces()).unwrap();
+        assert!(report.is_consistent(), "{:?}", report.divergences);
+
+        // Aborted and expired reservations run their compensation too.
+        failures.lock().unwrap().clear();
+        let undone = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let compensation: ReservationCompensation = {
+            let undone = undone.clone();
+            Arc::new(move |failure: &str| {
+                undone.lock().unwrap().push(failure.to_string());
+                Ok("released hold".to_string())
+            })
+        };
+        let reserved = gate
+            .reserve_with_compensation(&unit("e"), compensation.clone(), "charge", "payments")
+            .unwrap();
+        reserved.abort("worker crashed").unwrap();
+        assert!(!gate.is_consumed("e").unwrap());
+        gate.reserve_with_compensation(&unit("f"), compensation, "charge", "payments")
+            .unwrap();
+        clock.advance(301.0);
+        assert_eq!(gate.release_expired().unwrap(), 1);
+        assert!(!gate.is_consumed("f").unwrap());
+        let undone = undone.lock().unwrap().clone();
+        assert_eq!(undone[0], "worker crashed");
+        assert!(undone[1].starts_with("reservation "));
+        assert_eq!(traces().pop().unwrap().outcome, TraceOutcome::Compensated);
+    }
 }